@@ -1,15 +1,15 @@
 // src/api_structures/api_state.rs
-use std::sync::{Arc, Mutex};
+use actix::Addr;
 use crate::api_structures::managers::session_manager::SessionManager;
 
+/// Shared with every worker, cloning only clones the address.
+#[derive(Clone)]
 pub struct ApiState {
-    pub session_manager: Arc<Mutex<SessionManager>>,
+    pub session_manager: Addr<SessionManager>,
 }
 
 impl ApiState {
-    pub fn new() -> Self {
-        Self {
-            session_manager: SessionManager::new(),
-        }
+    pub fn new(session_manager: Addr<SessionManager>) -> Self {
+        Self { session_manager }
     }
-}
\ No newline at end of file
+}
@@ -3,6 +3,8 @@ use std::collections::HashMap;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
+use crate::api_structures::card_game::template::{compile_template, TemplateError};
+use crate::api_structures::card_game::validation::{DeckDiagnostic, DeckReport};
 use crate::api_structures::session::Player;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -42,17 +44,53 @@ pub struct TextInfo {
 pub enum Data {
     String { string: String },
     Integer { integer: i32 },
-    StateRefrence { ident: String },
+    Boolean { boolean: bool },
+    StateRefrence {
+        ident: String,
+        #[serde(default)]
+        selector: Option<Selector>, // individual states default to the current player
+    },
     TableRefrence { ident: String },
     ActionRefrence { ident: String },
-    Combinator { buff: Vec<Data> },
+    Expression {
+        op: Operator,
+        left: Box<Data>,
+        right: Box<Data>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type")]
+pub enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    Min,
+    Max,
+}
+
+impl Operator {
+    /// Returns `None` on overflow or division by zero.
+    pub fn apply(&self, left: i32, right: i32) -> Option<i32> {
+        match self {
+            Operator::Add => left.checked_add(right),
+            Operator::Subtract => left.checked_sub(right),
+            Operator::Multiply => left.checked_mul(right),
+            Operator::Divide => left.checked_div(right),
+            Operator::Modulo => left.checked_rem(right),
+            Operator::Min => Some(left.min(right)),
+            Operator::Max => Some(left.max(right)),
+        }
+    }
 }
 
 impl Data {
     pub fn get_ident(&self) -> Option<String> {
         match self {
             Data::String { string } => Some(string.clone()),
-            Data::StateRefrence { ident } => Some(ident.clone()),
+            Data::StateRefrence { ident, .. } => Some(ident.clone()),
             Data::TableRefrence { ident } => Some(ident.clone()),
             Data::ActionRefrence { ident } => Some(ident.clone()),
             _ => None,
@@ -77,6 +115,82 @@ pub enum Selector {
     None,
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type")]
+pub enum Comparison {
+    Equal,
+    NotEqual,
+    Greater,
+    GreaterOrEqual,
+    Lower,
+    LowerOrEqual,
+}
+
+impl Comparison {
+    /// Compares numerically when both sides are integers, otherwise falls back to comparing the text.
+    pub fn compare(&self, left: &str, right: &str) -> bool {
+        let ordering = match (left.parse::<i32>(), right.parse::<i32>()) {
+            (Ok(left), Ok(right)) => left.cmp(&right),
+            _ => left.cmp(right),
+        };
+        match self {
+            Comparison::Equal => ordering.is_eq(),
+            Comparison::NotEqual => ordering.is_ne(),
+            Comparison::Greater => ordering.is_gt(),
+            Comparison::GreaterOrEqual => ordering.is_ge(),
+            Comparison::Lower => ordering.is_lt(),
+            Comparison::LowerOrEqual => ordering.is_le(),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "type")]
+pub enum UpdateOperation {
+    Add,
+    Subtract,
+    Set,
+    Multiply,
+    Reset, // back to the value the state was declared with, `value` is ignored
+    Swap { with: Selector }, // only for individual states, `value` is ignored
+}
+
+impl UpdateOperation {
+    /// Arithmetic only applies to integer states, a mismatched `Set` keeps the current value.
+    pub fn apply(
+        &self,
+        current: &StateValue,
+        value: &StateValue,
+        constructor_value: &StateValue,
+    ) -> StateValue {
+        match (self, current, value) {
+            (UpdateOperation::Add, StateValue::Integer(current), StateValue::Integer(value)) => {
+                StateValue::Integer(current.saturating_add(*value))
+            }
+            (UpdateOperation::Subtract, StateValue::Integer(current), StateValue::Integer(value)) => {
+                StateValue::Integer(current.saturating_sub(*value))
+            }
+            (UpdateOperation::Multiply, StateValue::Integer(current), StateValue::Integer(value)) => {
+                StateValue::Integer(current.saturating_mul(*value))
+            }
+            (UpdateOperation::Set, _, _) if current.state_type() == value.state_type() => value.clone(),
+            (UpdateOperation::Reset, _, _) => constructor_value.clone(),
+            _ => current.clone(),
+        }
+    }
+
+    pub fn uses_value(&self) -> bool {
+        !matches!(self, UpdateOperation::Reset | UpdateOperation::Swap { .. })
+    }
+
+    pub fn is_arithmetic(&self) -> bool {
+        matches!(
+            self,
+            UpdateOperation::Add | UpdateOperation::Subtract | UpdateOperation::Multiply
+        )
+    }
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(tag = "type")]
 pub enum Action {
@@ -84,7 +198,7 @@ pub enum Action {
         ident: String,
         state: String,
         value: Data,
-        add: bool,
+        operation: UpdateOperation,
         selector: Selector,
     },
     Option {
@@ -102,6 +216,18 @@ pub enum Action {
         state: String,
         selector: Selector,
     },
+    // actions listed in `then`/`else` only run when their branch is taken,
+    // a segment pointing at the condition renders the text of the taken branch.
+    // An `UpdateState` still has to sit behind an `Option`, the validator rejects one listed here directly
+    Condition {
+        ident: String,
+        left: Data,
+        cmp: Comparison,
+        right: Data,
+        then: Vec<String>,
+        #[serde(rename = "else", default)]
+        otherwise: Vec<String>,
+    },
 }
 
 impl Action {
@@ -111,6 +237,7 @@ impl Action {
             Action::Option { ident, .. } => ident.clone(),
             Action::GetFromTable { ident, .. } => ident.clone(),
             Action::GetFromState { ident, .. } => ident.clone(),
+            Action::Condition { ident, .. } => ident.clone(),
         }
     }
 }
@@ -131,8 +258,90 @@ pub struct Table {
 pub struct Card {
     pub bg: String,
     pub general_text: String,
+    #[serde(default)]
+    pub template: Option<String>,
+    #[serde(default)]
     pub segments: Vec<Segment>,
     pub actions: Vec<Action>,
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(tag = "type")]
+pub enum StateType {
+    #[default]
+    Integer,
+    String,
+    Boolean,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum StateValue {
+    Integer(i32),
+    Boolean(bool),
+    String(String),
+}
+
+impl StateValue {
+    /// Reads a literal declared in the deck, `None` when it doesn't match the declared type.
+    pub fn from_data(data: &Data, state_type: &StateType) -> Option<Self> {
+        match (data, state_type) {
+            (Data::Integer { integer }, StateType::Integer) => Some(StateValue::Integer(*integer)),
+            (Data::String { string }, StateType::String) => Some(StateValue::String(string.clone())),
+            (Data::Boolean { boolean }, StateType::Boolean) => Some(StateValue::Boolean(*boolean)),
+            _ => None,
+        }
+    }
+
+    /// Parses text produced while rendering a card (action values, references) into the given type.
+    pub fn parse(value: &str, state_type: &StateType) -> Option<Self> {
+        match state_type {
+            StateType::Integer => value.trim().parse().ok().map(StateValue::Integer),
+            StateType::Boolean => value.trim().parse().ok().map(StateValue::Boolean),
+            StateType::String => Some(StateValue::String(value.to_string())),
+        }
+    }
+
+    pub fn state_type(&self) -> StateType {
+        match self {
+            StateValue::Integer(_) => StateType::Integer,
+            StateValue::Boolean(_) => StateType::Boolean,
+            StateValue::String(_) => StateType::String,
+        }
+    }
+
+    pub fn to_data(&self) -> Data {
+        match self {
+            StateValue::Integer(integer) => Data::Integer { integer: *integer },
+            StateValue::Boolean(boolean) => Data::Boolean { boolean: *boolean },
+            StateValue::String(string) => Data::String { string: string.clone() },
+        }
+    }
+
+    pub fn as_integer(&self) -> Option<i32> {
+        match self {
+            StateValue::Integer(integer) => Some(*integer),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for StateValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StateValue::Integer(integer) => write!(f, "{}", integer),
+            StateValue::Boolean(boolean) => write!(f, "{}", boolean),
+            StateValue::String(string) => write!(f, "{}", string),
+        }
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -140,15 +349,44 @@ pub struct State {
     pub ident: String,
     pub value: Data,
     pub individual: bool,
+    #[serde(default, rename = "type")]
+    pub state_type: StateType,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Meta {
     pub deck_name: String,
     pub id: Uuid,
-    pub scoreboard: ScoreBoard,
-    max_cards: i32,
-    max_players: i32,
+    #[serde(default)]
+    pub scoreboard: Option<ScoreBoard>,
+    #[serde(default)]
+    pub scoreboards: Vec<ScoreBoard>,
+    pub max_cards: i32, // 0 or less means the game only ends when someone finishes it
+    pub max_players: i32, // 0 or less means anyone can join
+    #[serde(default)]
+    pub min_players: i32,
+    #[serde(default)]
+    pub draw_mode: DrawMode,
+    #[serde(default)]
+    pub phases: Vec<Phase>,
+}
+
+/// Limits which cards can be drawn for a number of cards, e.g. the first 10 only from "warmup".
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Phase {
+    #[serde(default)]
+    pub cards: Option<usize>, // how many cards the phase lasts, None means until the end of the game
+    #[serde(default)]
+    pub tags: Vec<String>, // cards with any of these tags, empty means any card
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(tag = "type")]
+pub enum DrawMode {
+    #[default]
+    WithReplacement, // every card can show up again right away
+    Reshuffle,       // every card is played once, then the pile is reshuffled
+    FinishWhenEmpty, // every card is played once, then the game ends
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -163,6 +401,7 @@ pub enum ScoreBoardCondition {
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct ScoreElement {
+    id: Uuid,
     username: String,
     value: i32,
     position: i32,
@@ -171,55 +410,142 @@ pub struct ScoreElement {
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct RenderedScoreBoard {
     ident: String,
+    title: Option<String>,
     data: Vec<ScoreElement>,
+    counter: Option<i32>, // value of a shared state, shared states have no per player data
+    winners: Vec<String>,
+    game_over: bool,
 }
 
 impl Default for RenderedScoreBoard {
     fn default() -> Self {
         Self {
             ident: "Default".to_string(),
+            title: None,
             data: Vec::new(),
+            counter: None,
+            winners: Vec::new(),
+            game_over: false,
         }
     }
 }
 
-impl PartialEq for ScoreElement {
-    fn eq(&self, other: &Self) -> bool {
-        self.value == other.value
+impl RenderedScoreBoard {
+    pub fn is_game_over(&self) -> bool {
+        self.game_over
     }
 }
 
-impl Eq for ScoreElement {}
-
-impl PartialOrd for ScoreElement {
-    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
-        Some(self.value.cmp(&other.value))
-    }
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ScoreBoard {
+    #[serde(default)]
+    pub title: Option<String>,
+    pub state: Data,
+    pub value: Data, // this is the value that will be used for the condition, like ClosestTo will use this value to compare. In conditions like FirstToReach this value will be the target value, in conditions like Biggest this will be ignored as it will just find the biggest value
+    pub cond: ScoreBoardCondition,
+    #[serde(default)]
+    pub finish_on_reach: bool, // only used by FirstToReach, ends the game as soon as someone hits the target
+    #[serde(default)]
+    pub ranking: Ranking,
 }
 
-impl Ord for ScoreElement {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.value.cmp(&other.value)
-    }
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(tag = "type")]
+pub enum Ranking {
+    #[default]
+    Competition, // 0, 0, 2
+    Dense,       // 0, 0, 1
 }
 
-#[derive(Serialize, Deserialize, Debug, Clone)]
-pub struct ScoreBoard {
-    state: Data,
-    value: Data, // this is the value that will be used for the condition, like ClosestTo will use this value to compare. In conditions like FirstToReach this value will be the target value, in conditions like Biggest this will be ignored as it will just find the biggest value
-    cond: ScoreBoardCondition,
+impl Ranking {
+    /// Sorts players by `key` (smallest key first) and gives players with an equal key the same position.
+    pub fn rank<K: Ord>(
+        &self,
+        players: &[Player],
+        mut buffer: Vec<(Uuid, i32)>,
+        key: impl Fn(i32) -> K,
+    ) -> Vec<ScoreElement> {
+        buffer.sort_by_key(|(_, value)| key(*value));
+
+        let mut elements = Vec::new();
+        let mut position = 0;
+        for (i, (id, value)) in buffer.iter().enumerate() {
+            if i > 0 && key(buffer[i - 1].1) != key(*value) {
+                position = match self {
+                    Ranking::Competition => i as i32,
+                    Ranking::Dense => position + 1,
+                };
+            }
+            if let Some(player) = players.iter().find(|x| x.id == *id) {
+                elements.push(ScoreElement {
+                    id: player.id,
+                    username: player.username.clone(),
+                    value: *value,
+                    position,
+                });
+            }
+        }
+        elements
+    }
 }
 
 impl ScoreBoard {
+    fn target(&self) -> Result<i32, ()> {
+        match self.value {
+            Data::Integer { integer } => Ok(integer),
+            _ => Err(()),
+        }
+    }
+
+    /// Who is at or over a `FirstToReach` target right now, `None` while nobody is.
+    /// A shared state reaches it for the whole table, so nobody is named.
+    pub fn reached(
+        &self,
+        states: &HashMap<String, StateModule>,
+        players: &[Player],
+    ) -> Option<Vec<Uuid>> {
+        let ScoreBoardCondition::FirstToReach = self.cond else {
+            return None;
+        };
+        let target = self.target().ok()?;
+        match states.get(&self.state.get_ident()?)? {
+            StateModule::IndividualState { constructor_value, map } => {
+                let reached: Vec<Uuid> = players
+                    .iter()
+                    .filter(|player| {
+                        map.get(&player.id)
+                            .unwrap_or(constructor_value)
+                            .as_integer()
+                            .is_some_and(|value| value >= target)
+                    })
+                    .map(|player| player.id)
+                    .collect();
+                (!reached.is_empty()).then_some(reached)
+            }
+            StateModule::SharedState { value, .. } => (value.as_integer()? >= target).then(Vec::new),
+        }
+    }
+
+    /// `first_reached` is what `reached` returned when the target was first crossed, see `GameState`.
     pub fn generate_scoreboard(
         &self,
-        states: HashMap<String, StateModule>,
-        players: Vec<Player>,
+        states: &HashMap<String, StateModule>,
+        players: &[Player],
+        first_reached: Option<&[Uuid]>,
     ) -> Result<RenderedScoreBoard, ()> {
-        println!("{:#?}", self);
-        let score_state = states.get(&self.state.get_ident().ok_or(())?).ok_or(())?;
-        log::info!("{:#?}", states);
+        let ident = self.state.get_ident().ok_or(())?;
+        if let ScoreBoardCondition::None = self.cond {
+            return Ok(RenderedScoreBoard {
+                ident,
+                title: self.title.clone(),
+                ..RenderedScoreBoard::default()
+            });
+        }
+
+        let score_state = states.get(&ident).ok_or(())?;
         let mut score_elements: Vec<ScoreElement> = Vec::new();
+        let mut counter: Option<i32> = None;
+        let mut winners: Vec<String> = Vec::new();
         match score_state {
             StateModule::IndividualState {
                 constructor_value,
@@ -227,54 +553,48 @@ impl ScoreBoard {
             } => {
 
                 let mut buffer: Vec<(Uuid, i32)> = Vec::new();
-                for player in &players {
+                for player in players {
                     let plr_value = map.get(&player.id).unwrap_or(constructor_value);
-                    buffer.push((player.id, *plr_value));
-
+                    buffer.push((player.id, plr_value.as_integer().ok_or(())?));
                 }
-                buffer.sort_by(|a, b| a.1.cmp(&b.1));
 
                 match self.cond {
                     ScoreBoardCondition::Biggest => {
-                        let cloned_buffer = buffer.clone();
-                        cloned_buffer.iter().enumerate().for_each(|(i, (id, value))| {
-                            score_elements.push(ScoreElement {
-                                username: players
-                                    .iter()
-                                    .find(|x| x.id == *id)
-                                    .unwrap()
-                                    .username
-                                    .clone(),
-                                value: *value,
-                                position: i as i32,
-                            });
-                        });
-                    },
+                        score_elements = self.ranking.rank(players, buffer, std::cmp::Reverse);
+                    }
                     ScoreBoardCondition::Lowest => {
-                        let cloned_buffer = buffer.clone();
-                        buffer.reverse();
-                        cloned_buffer.iter().enumerate().for_each(|(i, (id, value))| {
-                            score_elements.push(ScoreElement {
-                                username: players
-                                  .iter()
-                                  .find(|x| x.id == *id)
-                                  .unwrap()
-                                  .username
-                                  .clone(),
-                                value: *value,
-                                position: i as i32,
-                            });
-                        });
+                        score_elements = self.ranking.rank(players, buffer, |value| value);
+                    }
+                    ScoreBoardCondition::Closest => {
+                        let target = self.target()?;
+                        score_elements = self.ranking.rank(players, buffer, |value| value.abs_diff(target));
                     }
-                    _ => { return Err(()) }
+                    ScoreBoardCondition::FirstToReach => {
+                        score_elements = self.ranking.rank(players, buffer, std::cmp::Reverse);
+                        let first = first_reached.unwrap_or_default();
+                        winners = score_elements
+                            .iter()
+                            .filter(|element| first.contains(&element.id))
+                            .map(|element| element.username.clone())
+                            .collect();
+                    }
+                    ScoreBoardCondition::None => {}
                 }
             }
-            _ => { }
+            StateModule::SharedState { value, .. } => {
+                // everyone shares the counter, so reaching the target is a win for the whole table
+                counter = Some(value.as_integer().ok_or(())?);
+            }
         }
+        let reached = matches!(self.cond, ScoreBoardCondition::FirstToReach) && first_reached.is_some();
 
         Ok(RenderedScoreBoard {
-            ident: self.state.get_ident().ok_or(())?,
+            ident,
+            title: self.title.clone(),
             data: score_elements,
+            counter,
+            winners,
+            game_over: self.finish_on_reach && reached,
         })
     }
 }
@@ -284,46 +604,125 @@ impl ScoreBoard {
 pub enum StateModule {
     SharedState {
         ident: String,
-        value: i32,
+        value: StateValue,
+        constructor_value: StateValue,
     },
     IndividualState {
-        constructor_value: i32,
-        map: HashMap<Uuid, i32>,
+        constructor_value: StateValue,
+        map: HashMap<Uuid, StateValue>,
     },
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct DeckBundle {
-    pub score_state: ScoreBoard,
+    pub score_boards: Vec<ScoreBoard>,
+    pub max_cards: i32,
+    pub max_players: i32,
+    pub min_players: i32,
+    pub draw_mode: DrawMode,
+    pub phases: Vec<Phase>,
     pub tables: HashMap<String, Vec<Value>>,
     pub states: HashMap<String, StateModule>,
     pub cards: Vec<Card>,
 }
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Deck {
-    meta: Meta,
-    tables: Vec<Table>,
-    states: Vec<State>,
-    cards: Vec<Card>,
+    pub meta: Meta,
+    pub tables: Vec<Table>,
+    pub states: Vec<State>,
+    pub cards: Vec<Card>,
+}
+
+/// Turns a bundle back into the deck it came from, minus the name and id, so it can be validated again.
+impl From<&DeckBundle> for Deck {
+    fn from(bundle: &DeckBundle) -> Self {
+        let mut tables: Vec<Table> = bundle
+            .tables
+            .iter()
+            .map(|(ident, values)| Table { ident: ident.clone(), values: values.clone() })
+            .collect();
+        tables.sort_by(|a, b| a.ident.cmp(&b.ident));
+
+        let mut states: Vec<State> = bundle
+            .states
+            .iter()
+            .map(|(ident, state)| {
+                let (constructor_value, individual) = match state {
+                    StateModule::SharedState { constructor_value, .. } => (constructor_value, false),
+                    StateModule::IndividualState { constructor_value, .. } => (constructor_value, true),
+                };
+                State {
+                    ident: ident.clone(),
+                    value: constructor_value.to_data(),
+                    individual,
+                    state_type: constructor_value.state_type(),
+                }
+            })
+            .collect();
+        states.sort_by(|a, b| a.ident.cmp(&b.ident));
+
+        Deck {
+            meta: Meta {
+                deck_name: String::new(),
+                id: Uuid::nil(),
+                scoreboard: None,
+                scoreboards: bundle.score_boards.clone(),
+                max_cards: bundle.max_cards,
+                max_players: bundle.max_players,
+                min_players: bundle.min_players,
+                draw_mode: bundle.draw_mode.clone(),
+                phases: bundle.phases.clone(),
+            },
+            tables,
+            states,
+            cards: bundle.cards.clone(),
+        }
+    }
 }
 
 impl Deck {
-    pub fn into_bundle(self) -> DeckBundle {
+    /// Replaces the segments of every card that has a template with the compiled ones.
+    pub fn compile_templates(&mut self) -> Result<(), Vec<TemplateError>> {
+        let mut errors = Vec::new();
+        for (idx, card) in self.cards.iter_mut().enumerate() {
+            if let Some(template) = &card.template {
+                match compile_template(idx, template) {
+                    Ok(segments) => card.segments = segments,
+                    Err(err) => errors.push(err),
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    pub fn into_bundle(mut self) -> Result<DeckBundle, DeckReport> {
+        let report = self.validate();
+        if !report.is_valid() {
+            return Err(report);
+        }
+
         let mut table_hash = HashMap::new();
         for table in self.tables {
             table_hash.insert(table.ident, table.values);
         }
         let mut state_hash = HashMap::new();
-        println!("{:p}", &self.states);
         for state in self.states {
+            // the validator already rejected values that don't match their declared type
+            let constructor_value = StateValue::from_data(&state.value, &state.state_type)
+                .ok_or_else(|| DeckReport {
+                    errors: vec![DeckDiagnostic::StateTypeMismatch { state: state.ident.clone() }],
+                    warnings: Vec::new(),
+                })?;
             if state.individual {
                 state_hash.insert(
                     state.ident,
                     StateModule::IndividualState {
-                        constructor_value: match state.value {
-                            Data::Integer { integer } => integer,
-                            _ => 0,
-                        },
+                        constructor_value,
                         map: HashMap::new(),
                     },
                 );
@@ -332,20 +731,144 @@ impl Deck {
                     state.ident.clone(),
                     StateModule::SharedState {
                         ident: state.ident.clone(),
-                        value: match state.value {
-                            Data::Integer { integer } => integer,
-                            _ => 0,
-                        },
+                        value: constructor_value.clone(),
+                        constructor_value,
                     },
                 );
             }
         }
 
-        DeckBundle {
-            score_state: self.meta.scoreboard,
+        Ok(DeckBundle {
+            score_boards: self.meta.scoreboard.into_iter().chain(self.meta.scoreboards).collect(),
+            max_cards: self.meta.max_cards,
+            max_players: self.meta.max_players,
+            min_players: self.meta.min_players,
+            draw_mode: self.meta.draw_mode,
+            phases: self.meta.phases,
             tables: table_hash,
             states: state_hash,
             cards: self.cards,
-        }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn positions(ranking: Ranking, values: &[i32]) -> Vec<(i32, i32)> {
+        let players: Vec<Player> = (0..values.len())
+            .map(|i| Player::new(Uuid::from_u128(i as u128), format!("p{}", i), false))
+            .collect();
+        let buffer = players.iter().map(|player| player.id).zip(values.iter().copied()).collect();
+        ranking
+            .rank(&players, buffer, std::cmp::Reverse)
+            .into_iter()
+            .map(|element| (element.value, element.position))
+            .collect()
+    }
+
+    #[test]
+    fn ranks_ties_at_the_top() {
+        let values = [5, 10, 3, 10];
+        assert_eq!(positions(Ranking::Competition, &values), vec![(10, 0), (10, 0), (5, 2), (3, 3)]);
+        assert_eq!(positions(Ranking::Dense, &values), vec![(10, 0), (10, 0), (5, 1), (3, 2)]);
+    }
+
+    #[test]
+    fn ranks_ties_in_the_middle() {
+        let values = [5, 3, 10, 5];
+        assert_eq!(positions(Ranking::Competition, &values), vec![(10, 0), (5, 1), (5, 1), (3, 3)]);
+        assert_eq!(positions(Ranking::Dense, &values), vec![(10, 0), (5, 1), (5, 1), (3, 2)]);
+    }
+
+    #[test]
+    fn ranks_ties_at_the_bottom() {
+        let values = [3, 10, 3, 7, 3];
+        assert_eq!(
+            positions(Ranking::Competition, &values),
+            vec![(10, 0), (7, 1), (3, 2), (3, 2), (3, 2)]
+        );
+        assert_eq!(positions(Ranking::Dense, &values), vec![(10, 0), (7, 1), (3, 2), (3, 2), (3, 2)]);
+    }
+
+    #[test]
+    fn ranks_everyone_tied() {
+        assert_eq!(positions(Ranking::Competition, &[4, 4, 4]), vec![(4, 0), (4, 0), (4, 0)]);
+        assert_eq!(positions(Ranking::Dense, &[4, 4, 4]), vec![(4, 0), (4, 0), (4, 0)]);
+    }
+
+    #[test]
+    fn ranks_by_distance_for_closest() {
+        let players: Vec<Player> = (0..3)
+            .map(|i| Player::new(Uuid::from_u128(i), format!("p{}", i), false))
+            .collect();
+        let buffer = vec![(players[0].id, 12), (players[1].id, 8), (players[2].id, 10)];
+        let ranked: Vec<(i32, i32)> = Ranking::Dense
+            .rank(&players, buffer, |value| value.abs_diff(10))
+            .into_iter()
+            .map(|element| (element.value, element.position))
+            .collect();
+        assert_eq!(ranked, vec![(10, 0), (12, 1), (8, 1)]);
+    }
+
+    #[test]
+    fn closest_survives_extreme_values() {
+        let players: Vec<Player> = (0..3)
+            .map(|i| Player::new(Uuid::from_u128(i), format!("p{}", i), false))
+            .collect();
+        let map = HashMap::from([
+            (players[0].id, StateValue::Integer(i32::MAX)),
+            (players[1].id, StateValue::Integer(i32::MIN)),
+            (players[2].id, StateValue::Integer(-9)),
+        ]);
+        let states = HashMap::from([(
+            "points".to_string(),
+            StateModule::IndividualState { constructor_value: StateValue::Integer(0), map },
+        )]);
+        let board = ScoreBoard {
+            title: None,
+            state: Data::StateRefrence { ident: "points".to_string(), selector: None },
+            value: Data::Integer { integer: -10 },
+            cond: ScoreBoardCondition::Closest,
+            finish_on_reach: false,
+            ranking: Ranking::Competition,
+        };
+        let ranked: Vec<(i32, i32)> = board
+            .generate_scoreboard(&states, &players, None)
+            .unwrap()
+            .data
+            .into_iter()
+            .map(|element| (element.value, element.position))
+            .collect();
+        assert_eq!(ranked, vec![(-9, 0), (i32::MIN, 1), (i32::MAX, 2)]);
+    }
+
+    #[test]
+    fn compares_numbers_numerically_and_the_rest_as_text() {
+        assert!(Comparison::Greater.compare("10", "9"));
+        assert!(Comparison::Lower.compare("-3", "2"));
+        assert!(Comparison::Lower.compare("10", "9a"));
+        assert!(Comparison::Equal.compare("beer", "beer"));
+        assert!(Comparison::NotEqual.compare("beer", "wine"));
+        assert!(Comparison::GreaterOrEqual.compare("5", "5"));
+        assert!(Comparison::LowerOrEqual.compare("apple", "banana"));
+    }
+
+    #[test]
+    fn operators_are_checked() {
+        assert_eq!(Operator::Add.apply(2, 3), Some(5));
+        assert_eq!(Operator::Subtract.apply(2, 3), Some(-1));
+        assert_eq!(Operator::Multiply.apply(4, -3), Some(-12));
+        assert_eq!(Operator::Divide.apply(7, 2), Some(3));
+        assert_eq!(Operator::Modulo.apply(7, 2), Some(1));
+        assert_eq!(Operator::Min.apply(7, 2), Some(2));
+        assert_eq!(Operator::Max.apply(7, 2), Some(7));
+
+        assert_eq!(Operator::Divide.apply(7, 0), None);
+        assert_eq!(Operator::Modulo.apply(7, 0), None);
+        assert_eq!(Operator::Add.apply(i32::MAX, 1), None);
+        assert_eq!(Operator::Multiply.apply(i32::MIN, -1), None);
+        assert_eq!(Operator::Divide.apply(i32::MIN, -1), None);
     }
 }
@@ -1 +1,5 @@
 pub mod deck;
+pub mod template;
+pub mod validation;
+#[cfg(test)]
+pub mod test_deck;
@@ -0,0 +1,139 @@
+use pest::error::{InputLocation, LineColLocation};
+use pest::Parser;
+use pest_derive::Parser;
+use serde::{Deserialize, Serialize};
+
+use crate::api_structures::card_game::deck::{Segment, TextElement};
+
+#[derive(Parser)]
+#[grammar = "grammar/segment.pest"]
+struct SegmentParser;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TemplateError {
+    pub card: usize,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+/// Compiles card text like `"{player} drinks {amount} sips"` into segments,
+/// every `{ident}` becomes a `Segment::Action` and the rest is kept as raw spans.
+pub fn compile_template(card: usize, template: &str) -> Result<Vec<Segment>, TemplateError> {
+    let pairs = SegmentParser::parse(Rule::result, template).map_err(|err| {
+        let (line, column) = match err.line_col {
+            LineColLocation::Pos(pos) => pos,
+            LineColLocation::Span(start, _) => start,
+        };
+        let offset = match err.location {
+            InputLocation::Pos(pos) => pos,
+            InputLocation::Span((start, _)) => start,
+        };
+        let message = match template[offset..].chars().next() {
+            Some('{') => "unclosed action reference".to_string(),
+            Some('}') => "unexpected closing brace".to_string(),
+            _ => err.variant.message().to_string(),
+        };
+        TemplateError {
+            card,
+            line,
+            column,
+            message,
+        }
+    })?;
+
+    let mut segments = Vec::new();
+    for pair in pairs.flatten() {
+        match pair.as_rule() {
+            Rule::outside_braces => segments.push(Segment::Raw {
+                string: TextElement::span {
+                    content: pair.as_str().to_string(),
+                    text_color: "white".to_string(),
+                    bold: false,
+                },
+            }),
+            Rule::inner_content => {
+                let ident = pair.as_str().trim();
+                if ident.is_empty() {
+                    let (line, column) = pair.as_span().start_pos().line_col();
+                    return Err(TemplateError {
+                        card,
+                        line,
+                        column,
+                        message: "empty action reference".to_string(),
+                    });
+                }
+                segments.push(Segment::Action {
+                    ident: ident.to_string(),
+                });
+            }
+            _ => {}
+        }
+    }
+
+    Ok(segments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn idents(segments: &[Segment]) -> Vec<String> {
+        segments
+            .iter()
+            .map(|segment| match segment {
+                Segment::Action { ident } => format!("{{{}}}", ident),
+                Segment::Raw { string: TextElement::span { content, .. } } => content.clone(),
+                other => panic!("unexpected segment {:?}", other),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn splits_text_and_references() {
+        let segments = compile_template(0, "{player} drinks { amount } sips").unwrap();
+        assert_eq!(idents(&segments), vec!["{player}", " drinks ", "{amount}", " sips"]);
+    }
+
+    #[test]
+    fn keeps_non_ascii_text() {
+        let segments = compile_template(0, "Żółw {gracz} pije łyk 🍺").unwrap();
+        assert_eq!(idents(&segments), vec!["Żółw ", "{gracz}", " pije łyk 🍺"]);
+    }
+
+    #[test]
+    fn reports_unclosed_reference() {
+        let err = compile_template(3, "a {b").unwrap_err();
+        assert_eq!((err.card, err.line, err.column), (3, 1, 3));
+        assert_eq!(err.message, "unclosed action reference");
+    }
+
+    #[test]
+    fn reports_stray_closing_brace() {
+        let err = compile_template(0, "a } b").unwrap_err();
+        assert_eq!((err.line, err.column), (1, 3));
+        assert_eq!(err.message, "unexpected closing brace");
+    }
+
+    #[test]
+    fn reports_empty_reference() {
+        for template in ["a {} b", "a {  } b"] {
+            let err = compile_template(0, template).unwrap_err();
+            assert_eq!((err.line, err.column), (1, 4), "{}", template);
+            assert_eq!(err.message, "empty action reference");
+        }
+    }
+
+    #[test]
+    fn reports_position_on_later_lines() {
+        let err = compile_template(0, "first line\nsecond {x} line\nthird } line").unwrap_err();
+        assert_eq!((err.line, err.column), (3, 7));
+        assert_eq!(err.message, "unexpected closing brace");
+    }
+
+    #[test]
+    fn counts_columns_in_characters() {
+        let err = compile_template(0, "łóż {").unwrap_err();
+        assert_eq!((err.line, err.column), (1, 5));
+    }
+}
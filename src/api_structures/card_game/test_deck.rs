@@ -0,0 +1,20 @@
+//! The deck every test starts from, tests override only the parts they check.
+
+use serde_json::{json, Value as Json};
+
+use crate::api_structures::card_game::deck::{Deck, DeckBundle};
+
+/// A valid deck with a `drinks` table, an individual `points` state and one plain card.
+pub fn deck() -> Json {
+    json!({
+        "meta": { "deck_name": "test", "id": "00000000-0000-0000-0000-000000000000", "max_cards": 0, "max_players": 0 },
+        "tables": [{ "ident": "drinks", "values": [{ "value": "beer", "tags": [] }] }],
+        "states": [{ "ident": "points", "value": { "type": "Integer", "integer": 0 }, "individual": true }],
+        "cards": [{ "bg": "b", "general_text": "g", "actions": [] }]
+    })
+}
+
+/// Bundles a deck for a test that isn't about validation, panics when it doesn't validate.
+pub fn bundle(deck: Json) -> DeckBundle {
+    serde_json::from_value::<Deck>(deck).unwrap().into_bundle().unwrap()
+}
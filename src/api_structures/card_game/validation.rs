@@ -0,0 +1,759 @@
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+
+use crate::api_structures::card_game::deck::{
+    Action, Card, Data, Deck, DeckBundle, DrawMode, ScoreBoardCondition, Segment, Selector, State, StateType,
+    StateValue, UpdateOperation, Value,
+};
+use crate::api_structures::card_game::template::TemplateError;
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "kind")]
+pub enum DeckDiagnostic {
+    // errors
+    InvalidTemplate {
+        card: usize,
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    UnknownSegmentAction {
+        card: usize,
+        ident: String,
+    },
+    InvalidOptionAction {
+        card: usize,
+        ident: String,
+        action: String,
+    },
+    UnknownTable {
+        card: usize,
+        ident: String,
+        table: String,
+    },
+    EmptyTable {
+        card: usize,
+        ident: String,
+        table: String,
+    },
+    UnknownState {
+        card: usize,
+        ident: String,
+        state: String,
+    },
+    InvalidConditionAction {
+        card: usize,
+        ident: String,
+        action: String,
+    },
+    /// Updates only apply through an `Option`, listed in a branch they would never run.
+    UpdateInConditionBranch {
+        card: usize,
+        ident: String,
+        action: String,
+    },
+    UnknownDataReference {
+        card: usize,
+        ident: String,
+        reference: String,
+    },
+    UnknownScoreboardState {
+        scoreboard: usize,
+        state: Option<String>,
+    },
+    ScoreboardStateNotInteger {
+        scoreboard: usize,
+        state: String,
+    },
+    StateTypeMismatch {
+        state: String,
+    },
+    InvalidStateOperation {
+        card: usize,
+        ident: String,
+        state: String,
+    },
+    UpdateTypeMismatch {
+        card: usize,
+        ident: String,
+        state: String,
+        expected: StateType,
+        found: StateType,
+    },
+    InvalidScoreboardTarget {
+        scoreboard: usize,
+    },
+
+    // warnings
+    DuplicateAction {
+        card: usize,
+        ident: String,
+    },
+    SegmentWithoutText {
+        card: usize,
+        ident: String,
+    },
+    UnusedAction {
+        card: usize,
+        ident: String,
+    },
+    NonIntegerUpdate {
+        card: usize,
+        ident: String,
+    },
+    SelectorMismatch {
+        card: usize,
+        ident: String,
+        state: String,
+    },
+    NoCards,
+    MaxCardsUnreachable {
+        max_cards: i32,
+        cards: usize,
+    },
+    EmptyPhase {
+        phase: usize,
+    },
+    InvalidPlayerLimits {
+        min_players: i32,
+        max_players: i32,
+    },
+}
+
+impl From<TemplateError> for DeckDiagnostic {
+    fn from(value: TemplateError) -> Self {
+        DeckDiagnostic::InvalidTemplate {
+            card: value.card,
+            line: value.line,
+            column: value.column,
+            message: value.message,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct DeckReport {
+    pub errors: Vec<DeckDiagnostic>,
+    pub warnings: Vec<DeckDiagnostic>,
+}
+
+impl DeckReport {
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+impl DeckBundle {
+    /// Bundles normally come out of a validated deck, this is for the ones that come from elsewhere, like a replayed log.
+    pub fn validate(&self) -> DeckReport {
+        Deck::from(self).validate()
+    }
+}
+
+impl Deck {
+    /// Compiles card templates and checks that every reference in the deck resolves,
+    /// so a broken deck is rejected before it ever reaches a `GameManager`.
+    pub fn validate(&mut self) -> DeckReport {
+        let mut report = DeckReport::default();
+
+        if let Err(errors) = self.compile_templates() {
+            report
+                .errors
+                .extend(errors.into_iter().map(DeckDiagnostic::from));
+        }
+
+        let tables: HashMap<&String, &Vec<Value>> =
+            self.tables.iter().map(|t| (&t.ident, &t.values)).collect();
+        let states: HashMap<&String, &State> =
+            self.states.iter().map(|s| (&s.ident, s)).collect();
+
+        for state in &self.states {
+            if StateValue::from_data(&state.value, &state.state_type).is_none() {
+                report.errors.push(DeckDiagnostic::StateTypeMismatch {
+                    state: state.ident.clone(),
+                });
+            }
+        }
+
+        let scoreboards = self.meta.scoreboard.iter().chain(self.meta.scoreboards.iter());
+        for (idx, scoreboard) in scoreboards.enumerate() {
+            let state = scoreboard.state.get_ident();
+            match state.as_ref().and_then(|state| states.get(state)) {
+                Some(declared) if declared.state_type != StateType::Integer => report.errors.push(
+                    DeckDiagnostic::ScoreboardStateNotInteger {
+                        scoreboard: idx,
+                        state: declared.ident.clone(),
+                    },
+                ),
+                Some(_) => {}
+                None => report
+                    .errors
+                    .push(DeckDiagnostic::UnknownScoreboardState { scoreboard: idx, state }),
+            }
+
+            if matches!(
+                scoreboard.cond,
+                ScoreBoardCondition::Closest | ScoreBoardCondition::FirstToReach
+            ) && !matches!(scoreboard.value, Data::Integer { .. })
+            {
+                report
+                    .errors
+                    .push(DeckDiagnostic::InvalidScoreboardTarget { scoreboard: idx });
+            }
+        }
+
+        if self.cards.is_empty() {
+            report.warnings.push(DeckDiagnostic::NoCards);
+        }
+
+        if self.meta.max_players > 0 && self.meta.min_players > self.meta.max_players {
+            report.errors.push(DeckDiagnostic::InvalidPlayerLimits {
+                min_players: self.meta.min_players,
+                max_players: self.meta.max_players,
+            });
+        }
+
+        for (idx, phase) in self.meta.phases.iter().enumerate() {
+            let has_cards = self.cards.iter().any(|card| {
+                card.weight > 0
+                    && (phase.tags.is_empty() || card.tags.iter().any(|tag| phase.tags.contains(tag)))
+            });
+            if !has_cards {
+                report.warnings.push(DeckDiagnostic::EmptyPhase { phase: idx });
+            }
+        }
+
+        if let DrawMode::FinishWhenEmpty = self.meta.draw_mode {
+            if self.meta.max_cards > 0 && self.meta.max_cards as usize > self.cards.len() {
+                report.warnings.push(DeckDiagnostic::MaxCardsUnreachable {
+                    max_cards: self.meta.max_cards,
+                    cards: self.cards.len(),
+                });
+            }
+        }
+
+        for (idx, card) in self.cards.iter().enumerate() {
+            validate_card(idx, card, &tables, &states, &mut report);
+        }
+
+        report
+    }
+}
+
+fn validate_card(
+    idx: usize,
+    card: &Card,
+    tables: &HashMap<&String, &Vec<Value>>,
+    states: &HashMap<&String, &State>,
+    report: &mut DeckReport,
+) {
+    let mut actions: HashMap<String, &Action> = HashMap::new();
+    for action in &card.actions {
+        if actions.insert(action.get_ident(), action).is_some() {
+            report.warnings.push(DeckDiagnostic::DuplicateAction {
+                card: idx,
+                ident: action.get_ident(),
+            });
+        }
+    }
+
+    let mut used: HashSet<String> = HashSet::new();
+
+    for segment in &card.segments {
+        if let Segment::Action { ident } = segment {
+            used.insert(ident.clone());
+            match actions.get(ident) {
+                Some(Action::GetFromTable { .. })
+                | Some(Action::GetFromState { .. })
+                | Some(Action::Condition { .. }) => {}
+                Some(_) => report.warnings.push(DeckDiagnostic::SegmentWithoutText {
+                    card: idx,
+                    ident: ident.clone(),
+                }),
+                None => report.errors.push(DeckDiagnostic::UnknownSegmentAction {
+                    card: idx,
+                    ident: ident.clone(),
+                }),
+            }
+        }
+    }
+
+    for action in &card.actions {
+        match action {
+            Action::Option { ident, actions: update_idents, .. } => {
+                used.insert(ident.clone());
+                for update_ident in update_idents {
+                    used.insert(update_ident.clone());
+                    if !matches!(actions.get(update_ident), Some(Action::UpdateState { .. })) {
+                        report.errors.push(DeckDiagnostic::InvalidOptionAction {
+                            card: idx,
+                            ident: ident.clone(),
+                            action: update_ident.clone(),
+                        });
+                    }
+                }
+            }
+            Action::GetFromTable { ident, table, tags } => match tables.get(table) {
+                Some(values) => {
+                    if !values
+                        .iter()
+                        .any(|value| tags.iter().all(|tag| value.tags.contains(tag)))
+                    {
+                        report.errors.push(DeckDiagnostic::EmptyTable {
+                            card: idx,
+                            ident: ident.clone(),
+                            table: table.clone(),
+                        });
+                    }
+                }
+                None => report.errors.push(DeckDiagnostic::UnknownTable {
+                    card: idx,
+                    ident: ident.clone(),
+                    table: table.clone(),
+                }),
+            },
+            Action::UpdateState {
+                ident,
+                state,
+                value,
+                operation,
+                selector,
+            } => {
+                validate_data(idx, ident, value, &actions, states, &mut used, report);
+                // swapping needs two players, so it only works on individual states
+                let bad_swap = |individual: bool| match operation {
+                    UpdateOperation::Swap { with } => !individual || matches!(with, Selector::None),
+                    _ => false,
+                };
+                match states.get(state) {
+                    Some(declared) => {
+                        let found = data_type(value, states).filter(|_| operation.uses_value());
+                        if declared.state_type != StateType::Integer && operation.is_arithmetic() {
+                            report.errors.push(DeckDiagnostic::InvalidStateOperation {
+                                card: idx,
+                                ident: ident.clone(),
+                                state: state.clone(),
+                            });
+                        } else if let Some(found) = found.filter(|found| *found != declared.state_type) {
+                            report.errors.push(DeckDiagnostic::UpdateTypeMismatch {
+                                card: idx,
+                                ident: ident.clone(),
+                                state: state.clone(),
+                                expected: declared.state_type.clone(),
+                                found,
+                            });
+                        } else if declared.state_type == StateType::Integer
+                            && operation.uses_value()
+                            && !is_numeric(value)
+                        {
+                            report.warnings.push(DeckDiagnostic::NonIntegerUpdate {
+                                card: idx,
+                                ident: ident.clone(),
+                            });
+                        }
+
+                        // updates on a shared state only apply without a selector and the other way round
+                        if declared.individual == matches!(selector, Selector::None)
+                            || bad_swap(declared.individual)
+                        {
+                            report.warnings.push(DeckDiagnostic::SelectorMismatch {
+                                card: idx,
+                                ident: ident.clone(),
+                                state: state.clone(),
+                            })
+                        }
+                    }
+                    None => report.errors.push(DeckDiagnostic::UnknownState {
+                        card: idx,
+                        ident: ident.clone(),
+                        state: state.clone(),
+                    }),
+                }
+            }
+            Action::GetFromState {
+                ident,
+                state,
+                selector,
+            } => match states.get(state) {
+                Some(declared) if declared.individual && matches!(selector, Selector::None) => {
+                    report.warnings.push(DeckDiagnostic::SelectorMismatch {
+                        card: idx,
+                        ident: ident.clone(),
+                        state: state.clone(),
+                    })
+                }
+                Some(_) => {}
+                None => report.errors.push(DeckDiagnostic::UnknownState {
+                    card: idx,
+                    ident: ident.clone(),
+                    state: state.clone(),
+                }),
+            },
+            Action::Condition {
+                ident,
+                left,
+                right,
+                then,
+                otherwise,
+                ..
+            } => {
+                used.insert(ident.clone());
+                for data in [left, right] {
+                    validate_data(idx, ident, data, &actions, states, &mut used, report);
+                }
+                for branch_ident in then.iter().chain(otherwise) {
+                    used.insert(branch_ident.clone());
+                    match actions.get(branch_ident) {
+                        None => report.errors.push(DeckDiagnostic::InvalidConditionAction {
+                            card: idx,
+                            ident: ident.clone(),
+                            action: branch_ident.clone(),
+                        }),
+                        Some(Action::UpdateState { .. }) => report.errors.push(DeckDiagnostic::UpdateInConditionBranch {
+                            card: idx,
+                            ident: ident.clone(),
+                            action: branch_ident.clone(),
+                        }),
+                        Some(_) => {}
+                    }
+                }
+            }
+        }
+    }
+
+    for action in &card.actions {
+        if !used.contains(&action.get_ident()) {
+            report.warnings.push(DeckDiagnostic::UnusedAction {
+                card: idx,
+                ident: action.get_ident(),
+            });
+        }
+    }
+}
+
+/// Type of the value `data` evaluates to, `None` when it's only known once the card is drawn.
+fn data_type(data: &Data, states: &HashMap<&String, &State>) -> Option<StateType> {
+    match data {
+        Data::Integer { .. } | Data::Expression { .. } => Some(StateType::Integer),
+        Data::String { .. } => Some(StateType::String),
+        Data::Boolean { .. } => Some(StateType::Boolean),
+        Data::StateRefrence { ident, .. } => states.get(ident).map(|state| state.state_type.clone()),
+        Data::TableRefrence { .. } | Data::ActionRefrence { .. } => None,
+    }
+}
+
+/// Whether `data` can be evaluated to an integer, updates whose value doesn't evaluate are skipped.
+fn is_numeric(data: &Data) -> bool {
+    match data {
+        Data::Integer { .. } | Data::StateRefrence { .. } | Data::ActionRefrence { .. } => true,
+        Data::Expression { left, right, .. } => is_numeric(left) && is_numeric(right),
+        _ => false,
+    }
+}
+
+fn validate_data(
+    idx: usize,
+    ident: &str,
+    data: &Data,
+    actions: &HashMap<String, &Action>,
+    states: &HashMap<&String, &State>,
+    used: &mut HashSet<String>,
+    report: &mut DeckReport,
+) {
+    match data {
+        Data::ActionRefrence { ident: reference } => {
+            used.insert(reference.clone());
+            if !actions.contains_key(reference) {
+                report.errors.push(DeckDiagnostic::UnknownDataReference {
+                    card: idx,
+                    ident: ident.to_string(),
+                    reference: reference.clone(),
+                });
+            }
+        }
+        Data::StateRefrence { ident: state, .. } if !states.contains_key(state) => {
+            report.errors.push(DeckDiagnostic::UnknownState {
+                card: idx,
+                ident: ident.to_string(),
+                state: state.clone(),
+            })
+        }
+        Data::Expression { left, right, .. } => {
+            validate_data(idx, ident, left, actions, states, used, report);
+            validate_data(idx, ident, right, actions, states, used, report);
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api_structures::card_game::test_deck;
+    use serde_json::{json, Value as Json};
+
+    /// Touches every kind of action, table and state once so each test can break one of them.
+    fn base() -> Json {
+        let mut deck = test_deck::deck();
+        deck["meta"]["scoreboards"] = json!([{
+            "state": { "type": "StateRefrence", "ident": "points" },
+            "value": { "type": "Integer", "integer": 0 },
+            "cond": { "type": "Biggest" }
+        }]);
+        deck["tables"][0]["values"] = json!([
+            { "value": "beer", "tags": ["cheap"] },
+            { "value": "wine", "tags": [] }
+        ]);
+        deck["states"].as_array_mut().unwrap().push(json!(
+            { "ident": "name", "value": { "type": "String", "string": "" }, "individual": false, "type": { "type": "String" } }
+        ));
+        deck["cards"] = json!([{
+            "bg": "b",
+            "general_text": "g",
+            "template": "{p} drinks {d}",
+            "actions": [
+                { "type": "GetFromState", "ident": "p", "state": "points", "selector": { "selector": "Current" } },
+                { "type": "GetFromTable", "ident": "d", "table": "drinks", "tags": [] },
+                { "type": "Option", "ident": "o", "display": "+1", "actions": ["u"] },
+                { "type": "UpdateState", "ident": "u", "state": "points", "value": { "type": "Integer", "integer": 1 }, "operation": { "type": "Add" }, "selector": { "selector": "Current" } }
+            ]
+        }]);
+        deck
+    }
+
+    fn validate(deck: Json) -> DeckReport {
+        serde_json::from_value::<Deck>(deck).unwrap().validate()
+    }
+
+    fn kinds(diagnostics: &[DeckDiagnostic]) -> Vec<String> {
+        diagnostics
+            .iter()
+            .map(|diagnostic| serde_json::to_value(diagnostic).unwrap()["kind"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    fn assert_error(deck: Json, kind: &str) {
+        let report = validate(deck);
+        assert!(kinds(&report.errors).contains(&kind.to_string()), "{:?}", report);
+    }
+
+    fn assert_warning(deck: Json, kind: &str) {
+        let report = validate(deck);
+        assert!(report.is_valid(), "{:?}", report);
+        assert!(kinds(&report.warnings).contains(&kind.to_string()), "{:?}", report);
+    }
+
+    #[test]
+    fn base_deck_is_clean() {
+        let report = validate(base());
+        assert!(report.errors.is_empty() && report.warnings.is_empty(), "{:?}", report);
+    }
+
+    #[test]
+    fn invalid_template() {
+        let mut deck = base();
+        deck["cards"][0]["template"] = json!("{p drinks");
+        assert_error(deck, "InvalidTemplate");
+    }
+
+    #[test]
+    fn unknown_segment_action() {
+        let mut deck = base();
+        deck["cards"][0]["template"] = json!("{p} drinks {d} and {nope}");
+        assert_error(deck, "UnknownSegmentAction");
+    }
+
+    #[test]
+    fn invalid_option_action() {
+        let mut deck = base();
+        deck["cards"][0]["actions"][2]["actions"] = json!(["u", "p"]);
+        assert_error(deck, "InvalidOptionAction");
+    }
+
+    #[test]
+    fn unknown_table() {
+        let mut deck = base();
+        deck["cards"][0]["actions"][1]["table"] = json!("nope");
+        assert_error(deck, "UnknownTable");
+    }
+
+    #[test]
+    fn empty_table() {
+        let mut deck = base();
+        deck["cards"][0]["actions"][1]["tags"] = json!(["cheap", "nope"]);
+        assert_error(deck, "EmptyTable");
+    }
+
+    #[test]
+    fn unknown_state() {
+        let mut deck = base();
+        deck["cards"][0]["actions"][0]["state"] = json!("nope");
+        assert_error(deck, "UnknownState");
+    }
+
+    #[test]
+    fn invalid_condition_action() {
+        let mut deck = base();
+        deck["cards"][0]["actions"].as_array_mut().unwrap().push(json!({
+            "type": "Condition", "ident": "c",
+            "left": { "type": "Integer", "integer": 1 }, "cmp": { "type": "Equal" }, "right": { "type": "Integer", "integer": 1 },
+            "then": ["nope"]
+        }));
+        assert_error(deck, "InvalidConditionAction");
+    }
+
+    #[test]
+    fn update_in_condition_branch() {
+        let mut deck = base();
+        deck["cards"][0]["actions"].as_array_mut().unwrap().push(json!({
+            "type": "Condition", "ident": "c",
+            "left": { "type": "Integer", "integer": 1 }, "cmp": { "type": "Equal" }, "right": { "type": "Integer", "integer": 1 },
+            "then": ["o"], "else": ["u"]
+        }));
+        assert_error(deck, "UpdateInConditionBranch");
+    }
+
+    #[test]
+    fn unknown_data_reference() {
+        let mut deck = base();
+        deck["cards"][0]["actions"][3]["value"] = json!({ "type": "ActionRefrence", "ident": "nope" });
+        assert_error(deck, "UnknownDataReference");
+    }
+
+    #[test]
+    fn unknown_scoreboard_state() {
+        let mut deck = base();
+        deck["meta"]["scoreboards"][0]["state"]["ident"] = json!("nope");
+        assert_error(deck, "UnknownScoreboardState");
+    }
+
+    #[test]
+    fn scoreboard_state_not_integer() {
+        let mut deck = base();
+        deck["meta"]["scoreboards"][0]["state"]["ident"] = json!("name");
+        assert_error(deck, "ScoreboardStateNotInteger");
+    }
+
+    #[test]
+    fn state_type_mismatch() {
+        let mut deck = base();
+        deck["states"][1]["value"] = json!({ "type": "Integer", "integer": 1 });
+        assert_error(deck, "StateTypeMismatch");
+    }
+
+    #[test]
+    fn invalid_state_operation() {
+        let mut deck = base();
+        deck["cards"][0]["actions"][3]["state"] = json!("name");
+        deck["cards"][0]["actions"][3]["selector"] = json!({ "selector": "None" });
+        assert_error(deck, "InvalidStateOperation");
+    }
+
+    #[test]
+    fn invalid_scoreboard_target() {
+        let mut deck = base();
+        deck["meta"]["scoreboards"][0]["cond"] = json!({ "type": "Closest" });
+        deck["meta"]["scoreboards"][0]["value"] = json!({ "type": "String", "string": "10" });
+        assert_error(deck, "InvalidScoreboardTarget");
+    }
+
+    #[test]
+    fn invalid_player_limits() {
+        let mut deck = base();
+        deck["meta"]["min_players"] = json!(5);
+        deck["meta"]["max_players"] = json!(2);
+        assert_error(deck, "InvalidPlayerLimits");
+    }
+
+    #[test]
+    fn duplicate_action() {
+        let mut deck = base();
+        let duplicate = deck["cards"][0]["actions"][1].clone();
+        deck["cards"][0]["actions"].as_array_mut().unwrap().push(duplicate);
+        assert_warning(deck, "DuplicateAction");
+    }
+
+    #[test]
+    fn segment_without_text() {
+        let mut deck = base();
+        deck["cards"][0]["template"] = json!("{p} drinks {d} {o}");
+        assert_warning(deck, "SegmentWithoutText");
+    }
+
+    #[test]
+    fn unused_action() {
+        let mut deck = base();
+        deck["cards"][0]["actions"].as_array_mut().unwrap().push(json!({
+            "type": "GetFromTable", "ident": "x", "table": "drinks", "tags": []
+        }));
+        assert_warning(deck, "UnusedAction");
+    }
+
+    #[test]
+    fn non_integer_update() {
+        let mut deck = base();
+        deck["cards"][0]["actions"][3]["value"] = json!({ "type": "TableRefrence", "ident": "drinks" });
+        assert_warning(deck, "NonIntegerUpdate");
+    }
+
+    #[test]
+    fn selector_mismatch() {
+        let mut deck = base();
+        deck["cards"][0]["actions"][3]["selector"] = json!({ "selector": "None" });
+        assert_warning(deck, "SelectorMismatch");
+    }
+
+    #[test]
+    fn no_cards() {
+        let mut deck = base();
+        deck["cards"] = json!([]);
+        assert_warning(deck, "NoCards");
+    }
+
+    #[test]
+    fn max_cards_unreachable() {
+        let mut deck = base();
+        deck["meta"]["draw_mode"] = json!({ "type": "FinishWhenEmpty" });
+        deck["meta"]["max_cards"] = json!(5);
+        assert_warning(deck, "MaxCardsUnreachable");
+    }
+
+    #[test]
+    fn empty_phase() {
+        let mut deck = base();
+        deck["meta"]["phases"] = json!([{ "cards": 3, "tags": ["warmup"] }]);
+        assert_warning(deck, "EmptyPhase");
+    }
+
+    #[test]
+    fn update_type_mismatch() {
+        let mut deck = base();
+        deck["states"].as_array_mut().unwrap().push(json!({
+            "ident": "out", "value": { "type": "Boolean", "boolean": false }, "individual": true, "type": { "type": "Boolean" }
+        }));
+        deck["cards"][0]["actions"][3]["state"] = json!("out");
+        deck["cards"][0]["actions"][3]["operation"] = json!({ "type": "Set" });
+        assert_error(deck.clone(), "UpdateTypeMismatch");
+
+        // a state reference carries the type of the state it reads
+        deck["cards"][0]["actions"][3]["state"] = json!("points");
+        deck["cards"][0]["actions"][3]["value"] = json!({ "type": "StateRefrence", "ident": "name" });
+        assert_error(deck.clone(), "UpdateTypeMismatch");
+
+        deck["cards"][0]["actions"][3]["state"] = json!("name");
+        deck["cards"][0]["actions"][3]["selector"] = json!({ "selector": "None" });
+        deck["cards"][0]["actions"][3]["value"] = json!({ "type": "Integer", "integer": 1 });
+        assert_error(deck, "UpdateTypeMismatch");
+    }
+
+    #[test]
+    fn ignored_update_values_are_not_type_checked() {
+        let mut deck = base();
+        deck["states"][1]["individual"] = json!(true);
+        deck["cards"][0]["actions"][3]["state"] = json!("name");
+        deck["cards"][0]["actions"][3]["operation"] = json!({ "type": "Reset" });
+        let report = validate(deck);
+        assert!(report.is_valid(), "{:?}", report);
+    }
+}
@@ -0,0 +1,55 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Reads a setting from the environment, `.env` is loaded in `main`. Unset or unparsable values fall back to `default`.
+fn env_var<T: FromStr>(name: &str, default: T) -> T {
+    std::env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+fn env_secs(name: &str, default: u64) -> Duration {
+    Duration::from_secs(env_var(name, default))
+}
+
+/// How long a disconnected player keeps their seat before being removed from the session.
+pub fn disconnect_grace_period() -> Duration {
+    env_secs("DISCONNECT_GRACE_SECS", 60)
+}
+
+/// How often the server pings every websocket client.
+pub fn heartbeat_interval() -> Duration {
+    env_secs("HEARTBEAT_INTERVAL_SECS", 5)
+}
+
+/// A client that hasn't answered for this long is considered gone and its connection is closed.
+pub fn client_timeout() -> Duration {
+    env_secs("CLIENT_TIMEOUT_SECS", 15)
+}
+
+/// Sessions nobody has touched for this long are closed by the reaper.
+pub fn session_idle_ttl() -> Duration {
+    env_secs("SESSION_IDLE_TTL_SECS", 30 * 60)
+}
+
+/// Sessions without any players are closed much sooner than busy ones.
+pub fn empty_session_ttl() -> Duration {
+    env_secs("EMPTY_SESSION_TTL_SECS", 5 * 60)
+}
+
+/// How often `SessionManager` looks for idle sessions.
+pub fn reaper_interval() -> Duration {
+    env_secs("REAPER_INTERVAL_SECS", 60)
+}
+
+/// How many arbiter threads sessions are spread over, defaults to one per core.
+pub fn session_arbiters() -> usize {
+    let cores = std::thread::available_parallelism().map_or(1, |cores| cores.get());
+    env_var("SESSION_ARBITERS", cores).max(1)
+}
+
+/// Malformed packets a single connection may send before it gets closed.
+pub fn max_invalid_packets() -> usize {
+    env_var("MAX_INVALID_PACKETS", 20)
+}
@@ -0,0 +1,219 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use chrono::{DateTime, Utc};
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::api_structures::card_game::deck::{DeckBundle, TextInfo};
+use crate::api_structures::card_game::validation::DeckReport;
+use crate::api_structures::id::UserId;
+use crate::api_structures::managers::game_manager::{CardOption, GameBundle, GameManager, StateUpdate};
+use crate::api_structures::session::{Player, Players, SessionRng};
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "event")]
+pub enum GameEvent {
+    SessionCreated { seed: u64, deck: DeckBundle },
+    PlayerJoined { id: UserId, username: String, is_host: bool },
+    PlayerLeft { id: UserId },
+    DeckChanged { deck: DeckBundle },
+    GameStarted,
+    /// `card` indexes the cards of the last `SessionCreated` or `DeckChanged` deck, not the shuffled pile.
+    CardDrawn { card: usize, text: TextInfo, options: Vec<CardOption> },
+    OptionChosen { option: Uuid, by: Option<UserId> },
+    StateChanged { updates: Vec<StateUpdate> },
+    GameFinished,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct LogEntry {
+    pub at: DateTime<Utc>,
+    #[serde(flatten)]
+    pub event: GameEvent,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct GameLog {
+    pub entries: Vec<LogEntry>,
+}
+
+impl GameLog {
+    pub fn new(seed: u64, deck: DeckBundle) -> Self {
+        let mut log = Self::default();
+        log.push(GameEvent::SessionCreated { seed, deck });
+        log
+    }
+
+    pub fn push(&mut self, event: GameEvent) {
+        self.entries.push(LogEntry { at: Utc::now(), event });
+    }
+
+    /// One entry per line, serialized lazily so the log can be streamed and appended to without re-parsing it.
+    pub fn into_json_lines(self) -> impl Iterator<Item = serde_json::Result<String>> {
+        self.entries
+            .into_iter()
+            .map(|entry| serde_json::to_string(&entry).map(|line| line + "\n"))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "error")]
+pub enum ReplayError {
+    MissingSessionCreated,
+    /// A deck in the log doesn't pass validation, `entry` is the index of the entry carrying it.
+    InvalidDeck { entry: usize, report: DeckReport },
+    /// The replayed game drew a different card than the log says, `entry` is the index of that entry.
+    Diverged { entry: usize },
+}
+
+/// Rebuilds the final `GameBundle` by feeding the log back into a `GameManager` seeded like the
+/// original session, every random choice is taken again in the same order.
+pub fn replay(entries: &[LogEntry]) -> Result<GameBundle, ReplayError> {
+    let Some(GameEvent::SessionCreated { seed, deck }) = entries.first().map(|entry| &entry.event) else {
+        return Err(ReplayError::MissingSessionCreated);
+    };
+    // the log comes from the client, its decks get the same checks as a deck sent to `/create`
+    for (idx, entry) in entries.iter().enumerate() {
+        if let GameEvent::SessionCreated { deck, .. } | GameEvent::DeckChanged { deck } = &entry.event {
+            let report = deck.validate();
+            if !report.is_valid() {
+                return Err(ReplayError::InvalidDeck { entry: idx, report });
+            }
+        }
+    }
+
+    let rng: SessionRng = Rc::new(RefCell::new(StdRng::seed_from_u64(*seed)));
+    let players = Rc::new(RefCell::new(Players::new(rng.clone())));
+    let mut game_manager = GameManager::init(deck.clone(), players.clone(), rng);
+
+    for (idx, entry) in entries.iter().enumerate().skip(1) {
+        match &entry.event {
+            GameEvent::SessionCreated { .. } => return Err(ReplayError::Diverged { entry: idx }),
+            GameEvent::PlayerJoined { id, username, is_host } => {
+                players
+                    .borrow_mut()
+                    .add_player(Player::new(*id, username.clone(), *is_host));
+                game_manager.regen();
+            }
+            GameEvent::PlayerLeft { id } => {
+                game_manager.remove_player(*id);
+                players.borrow_mut().remove_player(*id);
+                game_manager.regen();
+            }
+            GameEvent::DeckChanged { deck } => game_manager.change_deck(deck.clone()),
+            GameEvent::GameStarted => game_manager.start_game(),
+            GameEvent::CardDrawn { card, .. } => {
+                if game_manager.get_next_card().is_none() || game_manager.current_card() != *card {
+                    return Err(ReplayError::Diverged { entry: idx });
+                }
+            }
+            GameEvent::OptionChosen { option, .. } => {
+                if game_manager.resolve_state(*option).is_none() {
+                    return Err(ReplayError::Diverged { entry: idx });
+                }
+            }
+            // the updates are re-applied by `OptionChosen`, they are only logged for readers
+            GameEvent::StateChanged { .. } => {}
+            GameEvent::GameFinished => game_manager.reset_game_state(),
+        }
+    }
+
+    Ok(game_manager.bundle_state())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api_structures::card_game::test_deck;
+    use serde_json::json;
+
+    fn bundle() -> DeckBundle {
+        let mut deck = test_deck::deck();
+        deck["cards"] = json!([{
+            "bg": "b",
+            "general_text": "g",
+            "template": "{p} drinks {d}",
+            "actions": [
+                { "type": "GetFromState", "ident": "p", "state": "points", "selector": { "selector": "Current" } },
+                { "type": "GetFromTable", "ident": "d", "table": "drinks", "tags": [] }
+            ]
+        }]);
+        test_deck::bundle(deck)
+    }
+
+    fn log(bundle: DeckBundle, players: u128) -> GameLog {
+        let mut log = GameLog::new(1, bundle);
+        for n in 1..=players {
+            log.push(GameEvent::PlayerJoined { id: Uuid::from_u128(n), username: format!("p{}", n), is_host: n == 1 });
+        }
+        log.push(GameEvent::GameStarted);
+        log.push(GameEvent::CardDrawn {
+            card: 0,
+            text: TextInfo { bg: "b".to_string(), general_text: "g".to_string(), text: Vec::new() },
+            options: Vec::new(),
+        });
+        log
+    }
+
+    fn invalid_deck_entry(result: Result<GameBundle, ReplayError>) -> Option<usize> {
+        match result {
+            Err(ReplayError::InvalidDeck { entry, .. }) => Some(entry),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn replays_a_valid_log() {
+        let bundle = replay(&log(bundle(), 2).entries).unwrap();
+        assert_eq!(serde_json::to_value(bundle).unwrap()["cards_played"], json!(1));
+    }
+
+    #[test]
+    fn needs_session_created_first() {
+        let mut entries = log(bundle(), 2).entries;
+        entries.remove(0);
+        assert!(matches!(replay(&entries), Err(ReplayError::MissingSessionCreated)));
+    }
+
+    #[test]
+    fn rejects_a_deck_reading_a_missing_state() {
+        let mut deck = bundle();
+        deck.states.clear();
+        assert_eq!(invalid_deck_entry(replay(&log(deck, 2).entries)), Some(0));
+    }
+
+    #[test]
+    fn rejects_a_deck_drawing_from_an_empty_table() {
+        let mut deck = bundle();
+        deck.tables.insert("drinks".to_string(), Vec::new());
+        assert_eq!(invalid_deck_entry(replay(&log(deck, 2).entries)), Some(0));
+    }
+
+    #[test]
+    fn rejects_an_invalid_deck_change() {
+        let mut deck = bundle();
+        deck.tables.clear();
+        let mut log = log(bundle(), 2);
+        log.push(GameEvent::GameFinished);
+        log.push(GameEvent::DeckChanged { deck });
+        assert_eq!(invalid_deck_entry(replay(&log.entries)), Some(6));
+    }
+
+    #[test]
+    fn drawing_without_players_doesnt_panic() {
+        assert!(replay(&log(bundle(), 0).entries).is_ok());
+    }
+
+    #[test]
+    fn json_lines_hold_one_entry_each() {
+        let lines: Vec<String> = log(bundle(), 2).into_json_lines().map(Result::unwrap).collect();
+        assert_eq!(lines.len(), 5);
+        for line in &lines {
+            assert!(line.ends_with('\n') && !line.trim_end().contains('\n'));
+            serde_json::from_str::<LogEntry>(line).unwrap();
+        }
+    }
+}
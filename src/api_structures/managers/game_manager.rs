@@ -1,29 +1,34 @@
 use std::cell::RefCell;
 use crate::api_structures::card_game::deck::{Action, Card, Data, DeckBundle, RenderedScoreBoard, Segment, StateModule, TextElement, TextInfo, Value};
-use crate::api_structures::card_game::deck::{ScoreBoard, Selector};
-use crate::api_structures::id;
-use crate::api_structures::session::{Player, Players};
-use rand::rngs::ThreadRng;
+use crate::api_structures::card_game::deck::{DrawMode, Phase, ScoreBoard, Selector, StateType, StateValue, UpdateOperation};
+use crate::api_structures::session::{Players, SessionRng};
 use rand::seq::SliceRandom;
-use rand::thread_rng;
+use rand::Rng;
 use serde::Deserialize;
 use serde::Serialize;
 use std::collections::{HashMap, HashSet};
-use std::hash::Hash;
 use std::rc::Rc;
 use uuid::Uuid;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct GameBundle {
-    score_board: RenderedScoreBoard,
+    score_boards: Vec<RenderedScoreBoard>,
     current_idx: usize,
+    cards_played: usize,
     states: Vec<StateModule> }
 
+impl GameBundle {
+    pub fn is_game_over(&self) -> bool {
+        self.score_boards.iter().any(|board| board.is_game_over())
+    }
+}
+
 impl Default for GameBundle {
     fn default() -> Self {
         Self {
-            score_board: RenderedScoreBoard::default(),
+            score_boards: Vec::new(),
             current_idx: 69,
+            cards_played: 0,
             states: Vec::new(),
         }
     }
@@ -44,11 +49,15 @@ impl Intermediate {
     }
 }
 
+/// Players are resolved when the card is drawn, so the update hits whoever the card was about
+/// even though the turn has already moved on by the time an option is picked.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StateUpdate {
     pub ident: String,
-    pub modifier: i32,
-    pub selector: Selector,
+    pub operation: UpdateOperation,
+    pub value: StateValue,
+    pub player: Option<Uuid>,
+    pub swap_with: Option<Uuid>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -70,67 +79,166 @@ pub struct GameState {
     tables: HashMap<String, Vec<Value>>,
     states: HashMap<String, StateModule>,
     cards: Vec<Card>,
+    deck_order: Vec<usize>, // where each card of `cards` sits in the deck as it was sent, follows the shuffles
     card_count: usize,
     current: usize,
-    score_state: ScoreBoard,
+    score_boards: Vec<ScoreBoard>,
+    max_cards: i32,
+    max_players: i32,
+    min_players: i32,
+    draw_mode: DrawMode,
+    draw_pile: Vec<usize>,
+    phases: Vec<Phase>,
+    first_reached: Vec<Option<Vec<Uuid>>>, // per scoreboard, who crossed a FirstToReach target first
 }
 
 impl GameState {
     pub fn new(bundle: DeckBundle, players: Rc<RefCell<Players>>) -> Self {
+        let first_reached = vec![None; bundle.score_boards.len()];
+        let deck_order = (0..bundle.cards.len()).collect();
         Self {
             players,
             tables: bundle.tables,
             states: bundle.states,
             cards: bundle.cards,
+            deck_order,
             card_count: 0,
             current: 0,
-            score_state: bundle.score_state,
+            score_boards: bundle.score_boards,
+            max_cards: bundle.max_cards,
+            max_players: bundle.max_players,
+            min_players: bundle.min_players,
+            draw_mode: bundle.draw_mode,
+            draw_pile: Vec::new(),
+            phases: bundle.phases,
+            first_reached,
         }
     }
 
     pub fn reset(&mut self) {
         self.card_count = 0;
         self.current = 0;
+        self.draw_pile.clear();
+        self.first_reached = vec![None; self.score_boards.len()];
     }
 
     pub fn change_deck(&mut self, bundle: DeckBundle) {
         self.tables = bundle.tables;
         self.states = bundle.states;
-        self.score_state = bundle.score_state;
+        self.score_boards = bundle.score_boards;
+        self.deck_order = (0..bundle.cards.len()).collect();
         self.cards = bundle.cards;
+        self.max_cards = bundle.max_cards;
+        self.max_players = bundle.max_players;
+        self.min_players = bundle.min_players;
+        self.draw_mode = bundle.draw_mode;
+        self.draw_pile.clear();
+        self.phases = bundle.phases;
+        self.first_reached = vec![None; self.score_boards.len()];
     }
 
-    pub fn bundle_state(&self) -> GameBundle {
+    /// Called after every state change, once a target is crossed the players who got there
+    /// stay the winners even when others catch up later.
+    fn record_reached(&mut self) {
+        let players = self.players.borrow();
+        for (board, first) in self.score_boards.iter().zip(self.first_reached.iter_mut()) {
+            if first.is_none() {
+                *first = board.reached(&self.states, &players.players);
+            }
+        }
+    }
 
-        if let Ok(score_board) = self.score_state.generate_scoreboard(self.states.clone(), self.players.borrow().players.clone()) {
-            GameBundle {
-                score_board,
-                current_idx: self.current,
-                states: self.states.clone().into_values().filter_map(|v| match v {
-                    StateModule::SharedState { .. } => Some(v),
-                    _ => None,
-                }).collect()
+    /// Whether the card belongs to a phase with these tags and can be drawn at all.
+    fn drawable(&self, idx: usize, tags: &[String]) -> bool {
+        let card = &self.cards[idx];
+        card.weight > 0 && (tags.is_empty() || card.tags.iter().any(|tag| tags.contains(tag)))
+    }
+
+    /// Tags of the phase the game is in followed by the later phases, an empty list allows any card.
+    /// Once every phase is over any card can be drawn, unless the last one lasts until the end.
+    fn phase_tags(&self) -> Vec<Vec<String>> {
+        let mut start = 0;
+        let mut remaining = Vec::new();
+        for phase in &self.phases {
+            match phase.cards {
+                Some(length) if self.card_count >= start + length => start += length,
+                _ => remaining.push(phase.tags.clone()),
             }
-        } else {
-            panic!("Failed to generate scoreboard")
+        }
+        if self.phases.last().is_none_or(|phase| phase.cards.is_some()) {
+            remaining.push(Vec::new());
+        }
+        remaining
+    }
+
+    pub fn bundle_state(&self) -> GameBundle {
+        let players = self.players.borrow();
+        let score_boards = self
+            .score_boards
+            .iter()
+            .zip(&self.first_reached)
+            .filter_map(|(board, first)| {
+                board
+                    .generate_scoreboard(&self.states, &players.players, first.as_deref())
+                    .map_err(|_| log::error!("Failed to generate scoreboard {:#?}", board))
+                    .ok()
+            })
+            .collect();
+
+        // sorted so the same game always bundles the same way, replays compare equal
+        let mut states: Vec<(&String, &StateModule)> = self
+            .states
+            .iter()
+            .filter(|(_, v)| matches!(v, StateModule::SharedState { .. }))
+            .collect();
+        states.sort_by(|a, b| a.0.cmp(b.0));
+
+        GameBundle {
+            score_boards,
+            current_idx: self.current,
+            cards_played: self.card_count,
+            states: states.into_iter().map(|(_, v)| v.clone()).collect(),
         }
     }
 
 
 }
 
+const MAX_CONDITION_DEPTH: usize = 16;
+
+/// An action is active when no condition gates it, or when one of the active conditions gating it took its branch.
+fn is_active(
+    ident: &str,
+    gates: &HashMap<String, Vec<String>>,
+    taken: &HashMap<String, Vec<String>>,
+    depth: usize,
+) -> bool {
+    match gates.get(ident) {
+        None => true,
+        Some(conditions) => {
+            depth < MAX_CONDITION_DEPTH
+                && conditions.iter().any(|condition| {
+                    taken
+                        .get(condition)
+                        .is_some_and(|branch| branch.iter().any(|gated| gated == ident))
+                        && is_active(condition, gates, taken, depth + 1)
+                })
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct GameManager {
-    rng: ThreadRng,
+    rng: SessionRng,
     players: Rc<RefCell<Players>>,
     game_state: GameState,
     awaited_states: HashMap<Uuid, CardOption>,
 }
 
 impl GameManager {
-    pub fn init(bundle: DeckBundle, players: Rc<RefCell<Players>>) -> Self {
+    pub fn init(bundle: DeckBundle, players: Rc<RefCell<Players>>, rng: SessionRng) -> Self {
         Self {
-            rng: thread_rng(),
+            rng,
             game_state: GameState::new(bundle.clone(), players.clone()),
             awaited_states: HashMap::new(),
             players,
@@ -141,43 +249,119 @@ impl GameManager {
         self.regen_states(&*players.borrow());
     }
 
-    pub fn resolve_state(&mut self, id: Uuid) {
+    /// Applies the updates of an awaited option, `None` when the option isn't awaited.
+    pub fn resolve_state(&mut self, id: Uuid) -> Option<Vec<StateUpdate>> {
         log::info!("Resolving state {:#?}", id);
-        if let Some(option) = self.awaited_states.remove(&id) {
-            for update in option.updates {
-                match update.selector {
-                    Selector::None => {
-                        let state = self.game_state.states.get_mut(&update.ident).unwrap();
-                        match state {
-                            StateModule::SharedState { value, ident } => {
-                                *value += update.modifier;
-                            }
-                            _ => {}
-                        }
+        let option = self.awaited_states.remove(&id)?;
+        for update in option.updates.iter().cloned() {
+            let Some(state) = self.game_state.states.get_mut(&update.ident) else {
+                log::error!("Unknown state in update {:#?}", update);
+                continue;
+            };
+            match state {
+                StateModule::SharedState {
+                    value,
+                    constructor_value,
+                    ..
+                } => {
+                    if update.player.is_none() {
+                        *value = update.operation.apply(value, &update.value, constructor_value);
                     }
-                    _ => {
-                        let players = &*self.players.borrow();
-                        let player = players.get_player(update.selector);
-                        let state = self.game_state.states.get_mut(&update.ident).unwrap();
-                        match state {
-                            StateModule::IndividualState {
-                                constructor_value,
-                                map,
-                            } => {
-                                if let Some(value) = map.get_mut(&player.id) {
-                                    *value += update.modifier;
-                                }
-                            }
-                            _ => {}
+                }
+                StateModule::IndividualState {
+                    constructor_value,
+                    map,
+                } => {
+                    let Some(player) = update.player else {
+                        continue;
+                    };
+                    if let UpdateOperation::Swap { .. } = update.operation {
+                        if let Some(other) = update.swap_with {
+                            let current = map.get(&player).unwrap_or(constructor_value).clone();
+                            let swapped = map.get(&other).unwrap_or(constructor_value).clone();
+                            map.insert(player, swapped);
+                            map.insert(other, current);
                         }
+                    } else if let Some(value) = map.get_mut(&player) {
+                        *value = update.operation.apply(value, &update.value, constructor_value);
                     }
                 }
             }
         }
+        self.game_state.record_reached();
+        Some(option.updates)
     }
 
     pub fn start_game(&mut self) {
-        self.game_state.cards.shuffle(&mut self.rng);
+        let mut order: Vec<usize> = (0..self.game_state.cards.len()).collect();
+        order.shuffle(&mut *self.rng.borrow_mut());
+        let state = &mut self.game_state;
+        state.cards = order.iter().map(|idx| state.cards[*idx].clone()).collect();
+        state.deck_order = order.iter().map(|idx| state.deck_order[*idx]).collect();
+        self.refill_draw_pile();
+    }
+
+    /// Weighted pick among the candidates of a phase, cards with a weight of 0 are never picked.
+    fn pick_weighted(&self, candidates: &[usize], tags: &[String]) -> Option<usize> {
+        let cards = &self.game_state.cards;
+        let pool: Vec<usize> = candidates
+            .iter()
+            .copied()
+            .filter(|idx| self.game_state.drawable(*idx, tags))
+            .collect();
+
+        let rng = &mut *self.rng.borrow_mut();
+        pool.choose_weighted(rng, |idx| cards[*idx].weight).ok().copied()
+    }
+
+    /// Picks from the current phase, moving on to the next one when nothing in it can be drawn.
+    fn pick_in_phase(&self, candidates: &[usize], phases: &[Vec<String>]) -> Option<usize> {
+        phases.iter().find_map(|tags| self.pick_weighted(candidates, tags))
+    }
+
+    fn refill_draw_pile(&mut self) {
+        self.game_state.draw_pile = (0..self.game_state.cards.len()).collect();
+        self.game_state.draw_pile.shuffle(&mut *self.rng.borrow_mut());
+    }
+
+    /// Picks the next card according to the deck's draw mode, `None` once the game should end.
+    fn draw_card(&mut self) -> Option<Card> {
+        let max_cards = self.game_state.max_cards;
+        if max_cards > 0 && self.game_state.card_count >= max_cards as usize {
+            log::info!("Reached max cards: {}", max_cards);
+            return None;
+        }
+
+        let phases = self.game_state.phase_tags();
+        let idx = match self.game_state.draw_mode {
+            DrawMode::WithReplacement => {
+                let all: Vec<usize> = (0..self.game_state.cards.len()).collect();
+                self.pick_in_phase(&all, &phases)?
+            }
+            DrawMode::Reshuffle | DrawMode::FinishWhenEmpty => {
+                // a reshuffle keeps the game in its phase, an emptied pile moves it to the next one.
+                // Phases no card of the deck belongs to are skipped, refilling wouldn't bring them any
+                if let DrawMode::Reshuffle = self.game_state.draw_mode {
+                    let state = &self.game_state;
+                    let current = phases
+                        .iter()
+                        .find(|tags| (0..state.cards.len()).any(|idx| state.drawable(idx, tags)));
+                    let in_phase = current
+                        .is_some_and(|tags| state.draw_pile.iter().any(|idx| state.drawable(*idx, tags)));
+                    if current.is_some() && !in_phase {
+                        self.refill_draw_pile();
+                    }
+                }
+                let pile = self.game_state.draw_pile.clone();
+                let idx = self.pick_in_phase(&pile, &phases)?;
+                self.game_state.draw_pile.retain(|pile_idx| *pile_idx != idx);
+                idx
+            }
+        };
+
+        self.game_state.card_count += 1;
+        self.game_state.current = idx;
+        self.game_state.cards.get(idx).cloned()
     }
 
     pub fn regen_states(&mut self, players: &Players) {
@@ -195,7 +379,7 @@ impl GameManager {
 
 
                 for player_id in &player_ids {
-                    map.entry(*player_id).or_insert(*constructor_value);
+                    map.entry(*player_id).or_insert_with(|| constructor_value.clone());
                 }
             }
         }
@@ -227,93 +411,81 @@ impl GameManager {
         let mut bg = String::new();
         let mut general_text = String::new();
 
-        if let Some(card) = self.game_state.cards.choose(&mut self.rng) {
+        if let Some(card) = self.draw_card() {
             bg = card.bg.clone();
             general_text = card.general_text.clone();
             for action in card.actions.clone() {
                 if let Some((inter, ident)) = match action {
                     Action::GetFromTable { ident, table, tags } => {
-                        let filtered_table = self.game_state.tables.get(&table).map(|v| {
-                            if tags.is_empty() {
-                                v.clone()
-                            } else {
-                                v.iter()
-                                    .filter(|item| tags.iter().all(|tag| item.tags.contains(tag)))
-                                    .cloned()
-                                    .collect()
-                            }
+                        let value = self.game_state.tables.get(&table).and_then(|values| {
+                            let filtered: Vec<&Value> = values
+                                .iter()
+                                .filter(|item| tags.iter().all(|tag| item.tags.contains(tag)))
+                                .collect();
+                            filtered
+                                .choose(&mut *self.rng.borrow_mut())
+                                .map(|item| item.value.clone())
                         });
-
-                        if let Some(table) = filtered_table {
-                            let value = table.choose(&mut self.rng).unwrap().value.clone();
-                            Some((Intermediate::Value(value), ident))
-                        } else {
-                            Some((
-                                Intermediate::Value("Error".to_string()),
-                                ident,
-                            ))
-                        }
+                        // the deck is validated, a missing table or value only comes from a hand-edited log
+                        Some((Intermediate::Value(value.unwrap_or_else(|| "Error".to_string())), ident))
                     }
                     Action::GetFromState {
                         ident,
                         state,
                         selector,
-                    } => match selector {
-                        Selector::None => {
-                            let state = self.game_state.states.get(&state).unwrap();
-                            match state {
-                                StateModule::SharedState {
-                                    value,
-                                    ident: state_ident,
-                                } => Some((
-                                    Intermediate::Value(value.clone().to_string()),
-                                    state_ident.clone(),
-                                )),
-                                _ => Some((
-                                    Intermediate::Value("WYWYWYWYWYYW WYJEBAŁO SIE v3".to_string()),
-                                    ident,
-                                )),
-                            }
-                        }
-                        _ => {
-                            let players = self.players.borrow();
-                            let player = players.get_player(selector);
-                            let state = self.game_state.states.get(&state).unwrap();
-                            match state {
-                                StateModule::IndividualState {
-                                    constructor_value,
-                                    map,
-                                } => {
-                                    if let Some(value) = map.get(&player.id) {
-                                        Some((
-                                            Intermediate::Value(value.clone().to_string()),
-                                            ident,
-                                        ))
-                                    } else {
-                                        Some((
-                                            Intermediate::Value(
-                                                "WYWYWYWYWYYW WYJEBAŁO SIE V2".to_string(),
-                                            ),
-                                            ident,
-                                        ))
-                                    }
-                                }
-                                StateModule::SharedState {
-                                    value,
-                                    ident: state_ident,
-                                } => Some((
-                                    Intermediate::Value(value.clone().to_string()),
-                                    state_ident.clone(),
-                                )),
-                            }
-                        }
-                    },
+                    } => {
+                        let value = self
+                            .state_value(&state, selector)
+                            .map(|value| value.to_string())
+                            .unwrap_or_else(|| "Error".to_string());
+                        Some((Intermediate::Value(value), ident))
+                    }
                     _ => None,
                 } {
                     actions_cache.insert(ident.clone(), inter);
                 }
             }
 
+            // conditions are evaluated once every value they could compare against is known
+            let mut taken: HashMap<String, Vec<String>> = HashMap::new();
+            let mut gates: HashMap<String, Vec<String>> = HashMap::new();
+            for action in &card.actions {
+                if let Action::Condition { ident, left, cmp, right, then, otherwise } = action {
+                    let result = match (
+                        self.resolve_data(left, &actions_cache),
+                        self.resolve_data(right, &actions_cache),
+                    ) {
+                        (Some(left), Some(right)) => cmp.compare(&left, &right),
+                        _ => false,
+                    };
+                    for gated in then.iter().chain(otherwise) {
+                        gates.entry(gated.clone()).or_default().push(ident.clone());
+                    }
+                    taken.insert(ident.clone(), if result { then.clone() } else { otherwise.clone() });
+                }
+            }
+
+            let active: HashSet<String> = card
+                .actions
+                .iter()
+                .map(|action| action.get_ident())
+                .filter(|ident| is_active(ident, &gates, &taken, 0))
+                .collect();
+            actions_cache.retain(|ident, _| active.contains(ident));
+
+            for action in &card.actions {
+                if let Action::Condition { ident, .. } = action {
+                    if !active.contains(ident) {
+                        continue;
+                    }
+                    let text: Vec<String> = taken[ident]
+                        .iter()
+                        .filter_map(|branch| actions_cache.get(branch).and_then(|inter| inter.to_string()))
+                        .collect();
+                    actions_cache.insert(ident.clone(), Intermediate::Value(text.join(" ")));
+                }
+            }
+
             for segment in card.segments.clone() {
                 match segment {
                     Segment::Raw { string } => {
@@ -330,59 +502,91 @@ impl GameManager {
             }
 
             for action in card.actions.clone() {
-                if (actions_cache.contains_key(&action.get_ident())) {
+                if !active.contains(&action.get_ident()) {
                     continue;
-                } else {
-                    match action {
-                        Action::UpdateState {
-                            ident,
-                            state,
-                            value,
-                            add,
-                            selector,
-                        } => {
-                            state_updates.insert(
-                                ident.clone(),
-                                StateUpdate {
-                                    ident: ident.clone(),
-                                    modifier: match value {
-                                        Data::Integer { integer } => {
-                                            if !add {
-                                                integer * -1
-                                            } else {
-                                                integer
-                                            }
-                                        }
-                                        _ => 0,
-                                    },
-                                    selector,
-                                },
-                            );
+                }
+                if let Action::UpdateState {
+                    ident,
+                    state,
+                    value,
+                    operation,
+                    selector,
+                } = action
+                {
+                    let players = self.players.borrow();
+                    let resolve = |selector: &Selector| match selector {
+                        Selector::None => None,
+                        _ if players.players.is_empty() => None,
+                        _ => Some(players.get_player(selector.clone()).id),
+                    };
+                    let swap_with = match &operation {
+                        UpdateOperation::Swap { with } => resolve(with),
+                        _ => None,
+                    };
+                    let constructor_value = match self.game_state.states.get(&state) {
+                        Some(StateModule::SharedState { constructor_value, .. })
+                        | Some(StateModule::IndividualState { constructor_value, .. }) => constructor_value.clone(),
+                        None => {
+                            log::warn!("Skipping update {} on unknown state {}", ident, state);
+                            continue;
                         }
-                        Action::Option {
-                            ident,
-                            display,
-                            actions,
-                        } => {
-                            let mut updates = Vec::new();
-                            for action_ident in actions {
-                                if let Some(state_update) = state_updates.clone().get(&action_ident)
-                                {
-                                    updates.push(state_update.clone());
-                                }
-                            }
-                            decisions.push(CardOption {
-                                id: Uuid::new_v4(),
-                                display,
-                                updates,
-                            });
+                    };
+                    let state_type = constructor_value.state_type();
+                    let value = match self.evaluate_as(&value, &state_type, &actions_cache) {
+                        Some(value) => value,
+                        // Reset and Swap ignore the value, anything else can't be applied without one
+                        None if !operation.uses_value() => constructor_value,
+                        None => {
+                            log::warn!("Skipping update {} on {}, {:?} isn't a {:?}", ident, state, value, state_type);
+                            continue;
+                        }
+                    };
+                    state_updates.insert(
+                        ident.clone(),
+                        StateUpdate {
+                            ident: state,
+                            value,
+                            player: resolve(&selector),
+                            swap_with,
+                            operation,
+                        },
+                    );
+                }
+            }
+
+            // options go in a second pass so they can point at updates declared after them
+            for action in card.actions.clone() {
+                if !active.contains(&action.get_ident()) {
+                    continue;
+                }
+                if let Action::Option {
+                    display,
+                    actions,
+                    ..
+                } = action
+                {
+                    let mut updates = Vec::new();
+                    for action_ident in actions {
+                        if let Some(state_update) = state_updates.get(&action_ident) {
+                            updates.push(state_update.clone());
                         }
-                        _ => {}
                     }
+                    decisions.push(CardOption {
+                        id: uuid::Builder::from_random_bytes(self.rng.borrow_mut().gen()).into_uuid(),
+                        display,
+                        updates,
+                    });
                 }
             }
+        } else {
+            return None;
         }
 
+        self.awaited_states = decisions
+            .iter()
+            .map(|option| (option.id, option.clone()))
+            .collect();
+
         let players = self.players.borrow();
         players.consume();
 
@@ -397,6 +601,82 @@ impl GameManager {
             },
         })
     }
+
+    fn resolve_data(&self, data: &Data, actions_cache: &HashMap<String, Intermediate>) -> Option<String> {
+        match data {
+            Data::String { string } => Some(string.clone()),
+            Data::Integer { integer } => Some(integer.to_string()),
+            Data::Boolean { boolean } => Some(boolean.to_string()),
+            Data::ActionRefrence { ident } => actions_cache.get(ident).and_then(|inter| inter.to_string()),
+            Data::StateRefrence { ident, selector } => self
+                .state_value(ident, selector.clone().unwrap_or(Selector::Current))
+                .map(|value| value.to_string()),
+            Data::Expression { .. } => self.evaluate(data, actions_cache).map(|value| value.to_string()),
+            _ => None,
+        }
+    }
+
+    /// Evaluates `data` as an integer, state references read the state of the player picked by their selector.
+    fn evaluate(&self, data: &Data, actions_cache: &HashMap<String, Intermediate>) -> Option<i32> {
+        match data {
+            Data::Integer { integer } => Some(*integer),
+            Data::String { string } => string.trim().parse().ok(),
+            Data::ActionRefrence { ident } => actions_cache
+                .get(ident)
+                .and_then(|inter| inter.to_string())
+                .and_then(|value| value.trim().parse().ok()),
+            Data::StateRefrence { ident, selector } => self
+                .state_value(ident, selector.clone().unwrap_or(Selector::Current))?
+                .as_integer(),
+            Data::Expression { op, left, right } => op.apply(
+                self.evaluate(left, actions_cache)?,
+                self.evaluate(right, actions_cache)?,
+            ),
+            _ => None,
+        }
+    }
+
+    /// Evaluates `data` into a value of the given state type, used for the value of an update.
+    fn evaluate_as(
+        &self,
+        data: &Data,
+        state_type: &StateType,
+        actions_cache: &HashMap<String, Intermediate>,
+    ) -> Option<StateValue> {
+        match state_type {
+            StateType::Integer => self.evaluate(data, actions_cache).map(StateValue::Integer),
+            _ => StateValue::parse(&self.resolve_data(data, actions_cache)?, state_type),
+        }
+    }
+
+    fn state_value(&self, ident: &str, selector: Selector) -> Option<StateValue> {
+        match self.game_state.states.get(ident)? {
+            StateModule::SharedState { value, .. } => Some(value.clone()),
+            StateModule::IndividualState { constructor_value, map } => {
+                let players = self.players.borrow();
+                if players.players.is_empty() {
+                    return None;
+                }
+                let player = players.get_player(selector);
+                Some(map.get(&player.id).unwrap_or(constructor_value).clone())
+            }
+        }
+    }
+
+    /// Whether one more player fits in the deck's player limit.
+    pub fn has_room(&self, players: usize) -> bool {
+        self.game_state.max_players <= 0 || players < self.game_state.max_players as usize
+    }
+
+    /// Index of the last drawn card in the deck as it was sent, the shuffled order stays internal.
+    pub fn current_card(&self) -> usize {
+        self.game_state.deck_order[self.game_state.current]
+    }
+
+    pub fn min_players(&self) -> usize {
+        self.game_state.min_players.max(0) as usize
+    }
+
     pub fn change_deck(&mut self, bundle: DeckBundle) {
         log::info!("Changing deck: {:#?}", bundle);
         self.game_state.change_deck(bundle);
@@ -413,3 +693,495 @@ impl GameManager {
         bundle
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api_structures::card_game::test_deck;
+    use crate::api_structures::session::Player;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    use serde_json::{json, Value as Json};
+
+    fn deck(cards: Json) -> Json {
+        let mut deck = test_deck::deck();
+        deck["states"].as_array_mut().unwrap().push(json!(
+            { "ident": "counter", "value": { "type": "Integer", "integer": 0 }, "individual": false }
+        ));
+        deck["cards"] = cards;
+        deck
+    }
+
+    /// A card whose only option adds `amount` points to the player it was drawn for.
+    fn points_card(amount: i32) -> Json {
+        json!({
+            "bg": "b",
+            "general_text": "g",
+            "actions": [
+                { "type": "Option", "ident": "o", "display": "ok", "actions": ["u"] },
+                { "type": "UpdateState", "ident": "u", "state": "points", "value": { "type": "Integer", "integer": amount }, "operation": { "type": "Add" }, "selector": { "selector": "Current" } }
+            ]
+        })
+    }
+
+    fn new_manager(deck: Json, players: usize) -> (GameManager, Rc<RefCell<Players>>) {
+        let bundle = test_deck::bundle(deck);
+        let rng: SessionRng = Rc::new(RefCell::new(StdRng::seed_from_u64(7)));
+        let plrs = Rc::new(RefCell::new(Players::new(rng.clone())));
+        for i in 0..players {
+            plrs.borrow_mut()
+                .add_player(Player::new(Uuid::from_u128(i as u128 + 1), format!("p{}", i), false));
+        }
+        let mut manager = GameManager::init(bundle, plrs.clone(), rng);
+        manager.regen();
+        manager.start_game();
+        (manager, plrs)
+    }
+
+    /// Draws a card and picks its first option, returns the name of the player it was drawn for.
+    fn play(manager: &mut GameManager, players: &Rc<RefCell<Players>>) -> String {
+        let current = players.borrow().get_player(Selector::Current).username.clone();
+        let card = manager.get_next_card().unwrap();
+        manager.resolve_state(card.state_options[0].id).unwrap();
+        current
+    }
+
+    fn scoreboard(manager: &GameManager) -> Json {
+        serde_json::to_value(manager.bundle_state()).unwrap()["score_boards"][0].clone()
+    }
+
+    #[test]
+    fn first_to_reach_keeps_the_first_winner() {
+        let mut deck = deck(json!([points_card(3)]));
+        deck["meta"]["scoreboards"] = json!([{
+            "state": { "type": "StateRefrence", "ident": "points" },
+            "value": { "type": "Integer", "integer": 6 },
+            "cond": { "type": "FirstToReach" }
+        }]);
+        let (mut manager, players) = new_manager(deck, 2);
+
+        let first = play(&mut manager, &players);
+        play(&mut manager, &players);
+        assert_eq!(scoreboard(&manager)["winners"], json!([]));
+
+        assert_eq!(play(&mut manager, &players), first);
+        assert_eq!(scoreboard(&manager)["winners"], json!([first]));
+
+        // the other player reaching the target later doesn't share the win
+        play(&mut manager, &players);
+        assert_eq!(scoreboard(&manager)["winners"], json!([first]));
+        assert!(!manager.bundle_state().is_game_over());
+
+        manager.reset_game_state();
+        assert_eq!(scoreboard(&manager)["winners"], json!([]));
+    }
+
+    #[test]
+    fn first_to_reach_finishes_the_game_when_asked() {
+        let mut deck = deck(json!([points_card(6)]));
+        deck["meta"]["scoreboards"] = json!([{
+            "state": { "type": "StateRefrence", "ident": "points" },
+            "value": { "type": "Integer", "integer": 6 },
+            "cond": { "type": "FirstToReach" },
+            "finish_on_reach": true
+        }]);
+        let (mut manager, players) = new_manager(deck, 2);
+
+        assert!(!manager.bundle_state().is_game_over());
+        let first = play(&mut manager, &players);
+        assert!(manager.bundle_state().is_game_over());
+        assert_eq!(scoreboard(&manager)["winners"], json!([first]));
+    }
+
+    fn text(card: &CardResult) -> String {
+        card.text
+            .text
+            .iter()
+            .map(|element| match element {
+                TextElement::span { content, .. } => content.as_str(),
+                _ => "",
+            })
+            .collect()
+    }
+
+    fn condition(ident: &str, left: Json, cmp: &str, right: Json, then: Json, otherwise: Json) -> Json {
+        json!({ "type": "Condition", "ident": ident, "left": left, "cmp": { "type": cmp }, "right": right, "then": then, "else": otherwise })
+    }
+
+    fn branching_deck(points: i32) -> Json {
+        let mut deck = deck(json!([{
+            "bg": "b",
+            "general_text": "g",
+            "template": "{c}",
+            "actions": [
+                condition("c", json!({ "type": "StateRefrence", "ident": "points" }), "Greater", json!({ "type": "Integer", "integer": 5 }), json!(["rich", "o"]), json!(["poor"])),
+                { "type": "GetFromTable", "ident": "rich", "table": "rich", "tags": [] },
+                { "type": "GetFromTable", "ident": "poor", "table": "poor", "tags": [] },
+                { "type": "Option", "ident": "o", "display": "spend", "actions": ["u"] },
+                { "type": "UpdateState", "ident": "u", "state": "points", "value": { "type": "Integer", "integer": 5 }, "operation": { "type": "Subtract" }, "selector": { "selector": "Current" } }
+            ]
+        }]));
+        deck["tables"] = json!([
+            { "ident": "rich", "values": [{ "value": "champagne", "tags": [] }] },
+            { "ident": "poor", "values": [{ "value": "water", "tags": [] }] }
+        ]);
+        deck["states"][0]["value"]["integer"] = json!(points);
+        deck
+    }
+
+    #[test]
+    fn condition_takes_the_then_branch() {
+        let (mut manager, _) = new_manager(branching_deck(10), 2);
+        let card = manager.get_next_card().unwrap();
+        assert_eq!(text(&card), "champagne");
+        assert_eq!(card.state_options.len(), 1);
+    }
+
+    #[test]
+    fn condition_takes_the_else_branch() {
+        let (mut manager, _) = new_manager(branching_deck(5), 2);
+        let card = manager.get_next_card().unwrap();
+        assert_eq!(text(&card), "water");
+        // the option only lives in the `then` branch
+        assert!(card.state_options.is_empty());
+    }
+
+    /// `depth` conditions that are all true, each one gating the next, the last one gating `d`.
+    fn nested_deck(depth: usize) -> Json {
+        let mut actions: Vec<Json> = (0..depth)
+            .map(|i| {
+                let next = if i + 1 == depth { "d".to_string() } else { format!("c{}", i + 1) };
+                condition(&format!("c{}", i), json!({ "type": "Integer", "integer": 1 }), "Equal", json!({ "type": "Integer", "integer": 1 }), json!([next]), json!([]))
+            })
+            .collect();
+        actions.push(json!({ "type": "GetFromTable", "ident": "d", "table": "drinks", "tags": [] }));
+        deck(json!([{ "bg": "b", "general_text": "g", "template": "{d}", "actions": actions }]))
+    }
+
+    #[test]
+    fn nested_conditions_gate_their_actions() {
+        let (mut manager, _) = new_manager(nested_deck(3), 2);
+        assert_eq!(text(&manager.get_next_card().unwrap()), "beer");
+    }
+
+    #[test]
+    fn conditions_nested_too_deep_are_inactive() {
+        let (mut manager, _) = new_manager(nested_deck(MAX_CONDITION_DEPTH), 2);
+        assert_eq!(text(&manager.get_next_card().unwrap()), "beer");
+
+        let (mut manager, _) = new_manager(nested_deck(MAX_CONDITION_DEPTH + 1), 2);
+        assert_eq!(text(&manager.get_next_card().unwrap()), "");
+    }
+
+    #[test]
+    fn cyclic_conditions_are_inactive() {
+        let one = json!({ "type": "Integer", "integer": 1 });
+        let deck = deck(json!([{
+            "bg": "b",
+            "general_text": "g",
+            "template": "{d}",
+            "actions": [
+                condition("a", one.clone(), "Equal", one.clone(), json!(["b", "d"]), json!([])),
+                condition("b", one.clone(), "Equal", one, json!(["a"]), json!([])),
+                { "type": "GetFromTable", "ident": "d", "table": "drinks", "tags": [] }
+            ]
+        }]));
+        let (mut manager, _) = new_manager(deck, 2);
+        assert_eq!(text(&manager.get_next_card().unwrap()), "");
+    }
+
+    #[test]
+    fn is_active_follows_the_taken_branch() {
+        let gates = HashMap::from([("x".to_string(), vec!["c".to_string()])]);
+        let taken = HashMap::from([("c".to_string(), vec!["x".to_string()])]);
+        assert!(is_active("x", &gates, &taken, 0));
+        assert!(is_active("ungated", &gates, &taken, 0));
+
+        let taken = HashMap::from([("c".to_string(), Vec::new())]);
+        assert!(!is_active("x", &gates, &taken, 0));
+    }
+
+    fn expression(op: &str, left: Json, right: Json) -> Json {
+        json!({ "type": "Expression", "op": { "type": op }, "left": left, "right": right })
+    }
+
+    fn evaluate(manager: &GameManager, data: Json) -> Option<i32> {
+        manager.evaluate(&serde_json::from_value(data).unwrap(), &HashMap::new())
+    }
+
+    #[test]
+    fn evaluates_nested_expressions() {
+        let (manager, _) = new_manager(deck(json!([points_card(1)])), 2);
+        let data = expression(
+            "Add",
+            json!({ "type": "Integer", "integer": 1 }),
+            expression("Multiply", json!({ "type": "String", "string": "3" }), json!({ "type": "Integer", "integer": 4 })),
+        );
+        assert_eq!(evaluate(&manager, data), Some(13));
+        assert_eq!(evaluate(&manager, json!({ "type": "String", "string": "beer" })), None);
+    }
+
+    #[test]
+    fn expressions_fail_on_division_by_zero_and_overflow() {
+        let (manager, _) = new_manager(deck(json!([points_card(1)])), 2);
+        let zero = json!({ "type": "StateRefrence", "ident": "points" });
+        assert_eq!(evaluate(&manager, expression("Divide", json!({ "type": "Integer", "integer": 1 }), zero.clone())), None);
+        assert_eq!(evaluate(&manager, expression("Modulo", json!({ "type": "Integer", "integer": 1 }), zero)), None);
+        assert_eq!(
+            evaluate(&manager, expression("Add", json!({ "type": "Integer", "integer": i32::MAX }), json!({ "type": "Integer", "integer": 1 }))),
+            None
+        );
+    }
+
+    #[test]
+    fn updates_read_other_players_through_expressions() {
+        // "add half of the previous player's score"
+        let card = json!({
+            "bg": "b",
+            "general_text": "g",
+            "actions": [
+                { "type": "Option", "ident": "o", "display": "ok", "actions": ["u"] },
+                { "type": "UpdateState", "ident": "u", "state": "points", "operation": { "type": "Add" }, "selector": { "selector": "Current" },
+                  "value": expression("Divide", json!({ "type": "StateRefrence", "ident": "points", "selector": { "selector": "Previous" } }), json!({ "type": "Integer", "integer": 2 })) }
+            ]
+        });
+        let mut deck = deck(json!([card]));
+        deck["states"][0]["value"]["integer"] = json!(10);
+        let (mut manager, players) = new_manager(deck, 2);
+
+        let current = players.borrow().get_player(Selector::Current).id;
+        let card = manager.get_next_card().unwrap();
+        let updates = manager.resolve_state(card.state_options[0].id).unwrap();
+        assert_eq!(updates[0].player, Some(current));
+        assert_eq!(updates[0].value, StateValue::Integer(5));
+        assert_eq!(manager.state_value("points", Selector::Previous), Some(StateValue::Integer(15)));
+    }
+
+    #[test]
+    fn conditions_with_failing_expressions_take_the_else_branch() {
+        let card = json!({
+            "bg": "b",
+            "general_text": "g",
+            "template": "{c}",
+            "actions": [
+                condition("c", expression("Divide", json!({ "type": "Integer", "integer": 1 }), json!({ "type": "Integer", "integer": 0 })), "Equal", json!({ "type": "Integer", "integer": 0 }), json!(["d"]), json!([])),
+                { "type": "GetFromTable", "ident": "d", "table": "drinks", "tags": [] }
+            ]
+        });
+        let (mut manager, _) = new_manager(deck(json!([card])), 2);
+        assert_eq!(text(&manager.get_next_card().unwrap()), "");
+    }
+
+    #[test]
+    fn updates_whose_value_doesnt_evaluate_are_skipped() {
+        let card = json!({
+            "bg": "b",
+            "general_text": "g",
+            "template": "{d}",
+            "actions": [
+                { "type": "GetFromTable", "ident": "d", "table": "drinks", "tags": [] },
+                { "type": "Option", "ident": "o", "display": "ok", "actions": ["u"] },
+                { "type": "UpdateState", "ident": "u", "state": "points", "operation": { "type": "Set" }, "selector": { "selector": "Current" },
+                  "value": { "type": "ActionRefrence", "ident": "d" } }
+            ]
+        });
+        let mut deck = deck(json!([card]));
+        deck["states"][0]["value"]["integer"] = json!(10);
+        let (mut manager, _) = new_manager(deck, 2);
+
+        let card = manager.get_next_card().unwrap();
+        assert!(manager.resolve_state(card.state_options[0].id).unwrap().is_empty());
+        assert_eq!(manager.state_value("points", Selector::Previous), Some(StateValue::Integer(10)));
+    }
+
+    fn draw_deck(mode: &str, cards: usize, max_cards: i32) -> Json {
+        let mut deck = deck(json!(vec![points_card(1); cards]));
+        deck["meta"]["draw_mode"] = json!({ "type": mode });
+        deck["meta"]["max_cards"] = json!(max_cards);
+        deck
+    }
+
+    /// Indices of the cards drawn, stops early once the manager ends the game.
+    fn draw(manager: &mut GameManager, count: usize) -> Vec<usize> {
+        (0..count)
+            .map_while(|_| manager.get_next_card().map(|_| manager.current_card()))
+            .collect()
+    }
+
+    fn sorted(mut cards: Vec<usize>) -> Vec<usize> {
+        cards.sort();
+        cards
+    }
+
+    #[test]
+    fn with_replacement_keeps_drawing() {
+        let (mut manager, _) = new_manager(draw_deck("WithReplacement", 2, 0), 2);
+        let drawn = draw(&mut manager, 20);
+        assert_eq!(drawn.len(), 20);
+        assert!(drawn.iter().all(|card| *card < 2));
+    }
+
+    #[test]
+    fn reshuffle_plays_every_card_once_per_pile() {
+        let (mut manager, _) = new_manager(draw_deck("Reshuffle", 4, 0), 2);
+        let drawn = draw(&mut manager, 12);
+        assert_eq!(drawn.len(), 12);
+        for pile in drawn.chunks(4) {
+            assert_eq!(sorted(pile.to_vec()), vec![0, 1, 2, 3]);
+        }
+    }
+
+    #[test]
+    fn finish_when_empty_ends_after_the_last_card() {
+        let (mut manager, _) = new_manager(draw_deck("FinishWhenEmpty", 4, 0), 2);
+        assert_eq!(sorted(draw(&mut manager, 10)), vec![0, 1, 2, 3]);
+        assert!(manager.get_next_card().is_none());
+    }
+
+    #[test]
+    fn max_cards_ends_the_game() {
+        for mode in ["WithReplacement", "Reshuffle", "FinishWhenEmpty"] {
+            let (mut manager, _) = new_manager(draw_deck(mode, 4, 3), 2);
+            assert_eq!(draw(&mut manager, 10).len(), 3, "{}", mode);
+        }
+    }
+
+    #[test]
+    fn reset_starts_a_new_pile() {
+        let (mut manager, _) = new_manager(draw_deck("FinishWhenEmpty", 3, 0), 2);
+        assert_eq!(draw(&mut manager, 10).len(), 3);
+        manager.reset_game_state();
+        manager.start_game();
+        assert_eq!(sorted(draw(&mut manager, 10)), vec![0, 1, 2]);
+    }
+
+    fn named_card(name: &str, weight: u32, tags: &[&str]) -> Json {
+        json!({ "bg": "b", "general_text": name, "actions": [], "weight": weight, "tags": tags })
+    }
+
+    fn draw_names(manager: &mut GameManager, count: usize) -> Vec<String> {
+        (0..count)
+            .map_while(|_| manager.get_next_card().map(|card| card.text.general_text))
+            .collect()
+    }
+
+    #[test]
+    fn current_card_points_into_the_unshuffled_deck() {
+        let cards: Vec<Json> = (0..5).map(|i| named_card(&i.to_string(), 1, &[])).collect();
+        let mut deck = deck(json!(cards));
+        deck["meta"]["draw_mode"] = json!({ "type": "FinishWhenEmpty" });
+        let (mut manager, _) = new_manager(deck, 2);
+        for _ in 0..2 {
+            for _ in 0..5 {
+                let card = manager.get_next_card().unwrap();
+                assert_eq!(card.text.general_text, manager.current_card().to_string());
+            }
+            manager.reset_game_state();
+            manager.start_game();
+        }
+    }
+
+    #[test]
+    fn cards_with_zero_weight_are_never_drawn() {
+        let (mut manager, _) = new_manager(deck(json!([named_card("never", 0, &[]), named_card("always", 1, &[])])), 2);
+        assert!(draw_names(&mut manager, 50).iter().all(|name| name == "always"));
+
+        let (mut manager, _) = new_manager(deck(json!([named_card("never", 0, &[]), named_card("nope", 0, &[])])), 2);
+        assert!(manager.get_next_card().is_none());
+    }
+
+    #[test]
+    fn weights_skew_the_draw() {
+        let (mut manager, _) = new_manager(deck(json!([named_card("rare", 1, &[]), named_card("common", 20, &[])])), 2);
+        let drawn = draw_names(&mut manager, 400);
+        let rare = drawn.iter().filter(|name| *name == "rare").count();
+        assert!(rare > 0 && rare < 60, "{}", rare);
+    }
+
+    fn phased_deck(mode: &str) -> Json {
+        let mut deck = deck(json!([
+            named_card("warmup", 1, &["warmup"]),
+            named_card("warmup", 1, &["warmup"]),
+            named_card("main", 1, &["main"]),
+            named_card("main", 1, &["main"]),
+            named_card("spicy", 1, &["spicy"])
+        ]));
+        deck["meta"]["draw_mode"] = json!({ "type": mode });
+        deck["meta"]["phases"] = json!([{ "cards": 3, "tags": ["warmup"] }, { "tags": ["main"] }]);
+        deck
+    }
+
+    #[test]
+    fn phases_limit_which_cards_are_drawn() {
+        let (mut manager, _) = new_manager(phased_deck("WithReplacement"), 2);
+        let drawn = draw_names(&mut manager, 20);
+        assert!(drawn[..3].iter().all(|name| name == "warmup"), "{:?}", drawn);
+        assert!(drawn[3..].iter().all(|name| name == "main"), "{:?}", drawn);
+    }
+
+    #[test]
+    fn an_emptied_phase_moves_on_to_the_next_one() {
+        let (mut manager, _) = new_manager(phased_deck("FinishWhenEmpty"), 2);
+        // only two warmup cards for a three card phase, the third comes from the next phase,
+        // "spicy" isn't in any phase so it's never drawn
+        assert_eq!(draw_names(&mut manager, 10), vec!["warmup", "warmup", "main", "main"]);
+    }
+
+    #[test]
+    fn reshuffle_stays_in_its_phase() {
+        let (mut manager, _) = new_manager(phased_deck("Reshuffle"), 2);
+        let drawn = draw_names(&mut manager, 9);
+        assert_eq!(drawn[..3], ["warmup", "warmup", "warmup"]);
+        assert!(drawn[3..].iter().all(|name| name == "main"), "{:?}", drawn);
+    }
+
+    #[test]
+    fn reshuffle_skips_phases_without_cards() {
+        let mut deck = deck(json!([
+            named_card("a", 1, &["main"]),
+            named_card("b", 1, &["main"]),
+            named_card("spicy", 1, &["spicy"])
+        ]));
+        deck["meta"]["draw_mode"] = json!({ "type": "Reshuffle" });
+        deck["meta"]["phases"] = json!([{ "cards": 100, "tags": ["nope"] }, { "tags": ["main"] }]);
+        let (mut manager, _) = new_manager(deck, 2);
+        // the empty phase must not refill the pile on every draw, "main" still plays each card once per pile
+        for pile in draw_names(&mut manager, 12).chunks(2) {
+            let mut pile = pile.to_vec();
+            pile.sort();
+            assert_eq!(pile, ["a", "b"]);
+        }
+    }
+
+    #[test]
+    fn every_card_is_drawable_once_the_phases_are_over() {
+        let mut deck = phased_deck("FinishWhenEmpty");
+        deck["meta"]["phases"] = json!([{ "cards": 2, "tags": ["warmup"] }]);
+        let (mut manager, _) = new_manager(deck, 2);
+        let drawn = draw_names(&mut manager, 10);
+        assert_eq!(drawn.len(), 5);
+        assert_eq!(drawn[..2], ["warmup", "warmup"]);
+    }
+
+    #[test]
+    fn unvalidated_bundles_render_errors_instead_of_panicking() {
+        let card = json!({
+            "bg": "b",
+            "general_text": "g",
+            "template": "{p} {d}",
+            "actions": [
+                { "type": "GetFromState", "ident": "p", "state": "points", "selector": { "selector": "Current" } },
+                { "type": "GetFromTable", "ident": "d", "table": "drinks", "tags": [] }
+            ]
+        });
+        let mut bundle = test_deck::bundle(deck(json!([card])));
+        bundle.states.clear();
+        bundle.tables.insert("drinks".to_string(), Vec::new());
+
+        let rng: SessionRng = Rc::new(RefCell::new(StdRng::seed_from_u64(7)));
+        let players = Rc::new(RefCell::new(Players::new(rng.clone())));
+        let mut manager = GameManager::init(bundle, players, rng);
+        manager.start_game();
+        assert_eq!(text(&manager.get_next_card().unwrap()), "Error Error");
+    }
+}
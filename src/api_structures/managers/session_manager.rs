@@ -1,142 +1,220 @@
 use crate::api_structures::id::*;
-use crate::api_structures::messages::{AddPlayer, CloseSession, GetHostId, GetSessionId};
-use crate::api_structures::session::{Session, SessionCode};
+use crate::api_structures::game_log::LogEntry;
+use crate::api_structures::config;
+use crate::api_structures::messages::{AddPlayer, CloseSession, GetLog, GetSessionLog, InitSession, JoinSession, ListSessions, SessionActivity, Shutdown, UnwrapCode};
+use crate::api_structures::session::{Session, SessionCode, SessionError, SessionState};
 use crate::api_structures::session_connection::SessionConnection;
-use actix::{ spawn, Actor, Addr, Context, Handler};
-use futures::future::join_all;
+use actix::{Actor, Addr, Arbiter, ArbiterHandle, AsyncContext, Context, Handler, ResponseFuture};
+use rand::{thread_rng, Rng};
 use std::collections::HashMap;
-use std::sync::{Arc, Mutex};
-use uuid::Uuid;
-use crate::api_structures::card_game::deck::Deck;
+use std::time::Instant;
+
+/// Everything the manager needs to know about a session without asking the actor.
+#[derive(Clone, Debug)]
+pub struct SessionEntry {
+    pub addr: Addr<Session>,
+    pub code: SessionCode,
+    pub host_id: UserId,
+    pub state: SessionState,
+    pub players: usize,
+    last_seen: Instant,
+}
+
+impl SessionEntry {
+    fn expired(&self, now: Instant) -> bool {
+        let ttl = if self.players == 0 {
+            config::empty_session_ttl()
+        } else {
+            config::session_idle_ttl()
+        };
+        now.duration_since(self.last_seen) > ttl
+    }
+}
 
-#[derive(Clone)]
+/// Owns every session, HTTP handlers only ever talk to it through messages.
 pub struct SessionManager {
-    pub sessions: Arc<Mutex<Vec<Addr<Session>>>>,
-    pub session_codes: Arc<Mutex<HashMap<SessionCode, SessionId>>>,
+    sessions: HashMap<SessionId, SessionEntry>,
+    session_codes: HashMap<SessionCode, SessionId>,
+    /// Sessions are started round-robin on these so they don't all share the manager's thread.
+    arbiters: Vec<Arbiter>,
+    next_arbiter: usize,
 }
 
 #[derive(Debug)]
 pub enum SessionManagerError {
     UserSessionInstanceAlreadyExists,
     NoActiveSessions,
+    SessionNotFound,
+    NotHost,
+    JoinRejected(SessionError),
 }
 
 impl Actor for SessionManager {
     type Context = Context<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        ctx.run_interval(config::reaper_interval(), |manager, _ctx| {
+            manager.reap_idle_sessions();
+        });
+    }
+
+    fn stopped(&mut self, _ctx: &mut Self::Context) {
+        for arbiter in &self.arbiters {
+            arbiter.stop();
+        }
+    }
 }
 
 impl SessionManager {
-    pub fn new() -> Arc<Mutex<Self>> {
-        Arc::new(Mutex::new(Self {
-            sessions: Arc::new(Mutex::new(Vec::new())),
-            session_codes: Arc::new(Mutex::new(HashMap::new())),
-        }))
+    pub fn new() -> Self {
+        Self {
+            sessions: HashMap::new(),
+            session_codes: HashMap::new(),
+            arbiters: Vec::new(),
+            next_arbiter: 0,
+        }
     }
 
-    pub async fn init_session(
-        &mut self,
-        host_id: UserId,
-        username: String,
-        deck: Deck,
-    ) -> Result<(SessionId, SessionCode), SessionManagerError> {
-        let sessions = self.sessions.lock().expect("Failed to lock sessions");
-        for session in sessions.iter() {
-            let session_host_id = session
-                .send(GetHostId())
-                .await
-                .expect("Failed to get host id");
-            if Uuid::parse_str(&session_host_id).expect("Failed to parse UUID") == host_id {
-                return Err(SessionManagerError::UserSessionInstanceAlreadyExists);
-            }
+    /// Hands out arbiters round-robin, spawning them on first use up to `config::session_arbiters`.
+    fn next_arbiter(&mut self) -> ArbiterHandle {
+        if self.arbiters.len() < config::session_arbiters() {
+            self.arbiters.push(Arbiter::new());
+        }
+        let arbiter = &self.arbiters[self.next_arbiter % self.arbiters.len()];
+        self.next_arbiter = self.next_arbiter.wrapping_add(1);
+        arbiter.handle()
+    }
+
+    /// Shuts down sessions that went quiet for longer than their TTL.
+    fn reap_idle_sessions(&mut self) {
+        let now = Instant::now();
+        let expired: Vec<SessionId> = self
+            .sessions
+            .iter()
+            .filter(|(_, entry)| entry.expired(now))
+            .map(|(id, _)| *id)
+            .collect();
+
+        for id in expired {
+            log::info!("Reaping idle session: {:#?}", id);
+            self.close_session(id);
         }
+    }
+
+    /// The only way a session goes away: forgets its entry and code, then stops the actor.
+    pub fn close_session(&mut self, id: SessionId) {
+        let Some(entry) = self.sessions.remove(&id) else {
+            return;
+        };
+        self.session_codes.remove(&entry.code);
+        entry.addr.do_send(Shutdown);
+    }
+
+    fn session_addr(&self, session_id: SessionId) -> Option<Addr<Session>> {
+        self.sessions.get(&session_id).map(|entry| entry.addr.clone())
+    }
+}
 
-        let man_addr = self.clone().start();
+impl Handler<InitSession> for SessionManager {
+    type Result = Result<(SessionId, SessionCode, u64), SessionManagerError>;
 
-        drop(sessions);
+    fn handle(&mut self, msg: InitSession, ctx: &mut Self::Context) -> Self::Result {
+        if self.sessions.values().any(|entry| entry.host_id == msg.host_id) {
+            return Err(SessionManagerError::UserSessionInstanceAlreadyExists);
+        }
 
         let mut code = SessionCode::gen();
-        let mut codes = self.session_codes.lock().expect("Failed to lock sessions");
-        while codes
-            .keys()
-            .into_iter()
-            .collect::<Vec<&SessionCode>>()
-            .contains(&&code)
-        {
+        while self.session_codes.contains_key(&code) {
             code.regen();
         }
 
-        let (addr, id) = Session::init(host_id, username, man_addr, code.clone(), deck).await;
+        let seed = msg.seed.unwrap_or_else(|| thread_rng().gen());
+        let arbiter = self.next_arbiter();
+        let (addr, id) = Session::init(&arbiter, msg.host_id, msg.username, ctx.address(), code.clone(), msg.deck, seed);
+
+        self.session_codes.insert(code.clone(), id);
+        self.sessions.insert(
+            id,
+            SessionEntry {
+                addr,
+                code: code.clone(),
+                host_id: msg.host_id,
+                state: SessionState::Lobby,
+                players: 0,
+                last_seen: Instant::now(),
+            },
+        );
+        Ok((id, code, seed))
+    }
+}
 
-        codes.insert(code.clone(), id);
+impl Handler<UnwrapCode> for SessionManager {
+    type Result = Option<SessionId>;
 
-        self.sessions.lock().expect("Failed to lock sessions").push(addr.clone());
-        Ok((id, code.clone()))
+    fn handle(&mut self, msg: UnwrapCode, _ctx: &mut Self::Context) -> Self::Result {
+        self.session_codes.get(&msg.0).copied()
     }
-    pub fn unwrap_code(&self, code: SessionCode) -> Option<String> {
-        let session_codes = self.session_codes.lock().unwrap();
+}
 
-        if let Some(code) = session_codes.get(&code) {
-            Some(code.to_string())
-        } else {
-            None
-        }
+impl Handler<ListSessions> for SessionManager {
+    type Result = Vec<SessionId>;
+
+    fn handle(&mut self, _msg: ListSessions, _ctx: &mut Self::Context) -> Self::Result {
+        self.sessions.keys().copied().collect()
     }
-    pub async fn join_session(
-        &mut self,
-        session_id: SessionId,
-        user_id: UserId,
-        username: String,
-    ) -> Option<SessionConnection> {
-        let sessions = self.sessions.lock().expect("Failed to lock sessions");
-        for session in sessions.iter() {
-            let session_id_res = session
-                .send(GetSessionId())
+}
+
+impl Handler<JoinSession> for SessionManager {
+    type Result = ResponseFuture<Result<SessionConnection, SessionManagerError>>;
+
+    fn handle(&mut self, msg: JoinSession, _ctx: &mut Self::Context) -> Self::Result {
+        let session = self.session_addr(msg.session_id);
+        // awaited outside the manager so a busy session doesn't hold up everyone else
+        Box::pin(async move {
+            let session = session.ok_or(SessionManagerError::SessionNotFound)?;
+            session
+                .send(AddPlayer {
+                    id: msg.user_id,
+                    username: msg.username,
+                    is_host: false,
+                    session_addr: session.clone(),
+                })
                 .await
-                .expect("Failed to get session id");
-            if Uuid::parse_str(&session_id_res).expect("Failed to parse UUID") == session_id {
-                let conn = session
-                    .send(AddPlayer {
-                        id: user_id,
-                        username,
-                        is_host: false,
-                        session_addr: session.clone(),
-                    })
-                    .await
-                    .expect("Failed to add player")
-                    .expect("Failed to add player");
-
-                return Some(conn);
-            }
-        }
-        None
+                .map_err(|_| SessionManagerError::SessionNotFound)?
+                .map_err(SessionManagerError::JoinRejected)
+        })
     }
-    pub async fn get_games(&self) -> Vec<Uuid> {
-        let sessions = self.sessions.clone();
-
-        // Spawning an async block to run the tasks
-        let ids = spawn(async move {
-            let session_ids: Vec<Option<Uuid>> = {
-                let sessions = sessions.lock().unwrap();
-                let futures = sessions.iter().map(|session| {
-                    let session = session.clone();
-                    async move {
-                        let session_id_res = session.send(GetSessionId()).await.ok();
-                        session_id_res.and_then(|id| Uuid::parse_str(&id).ok())
-                    }
-                });
-                join_all(futures).await
-            };
-
-            session_ids
-                .into_iter()
-                .filter_map(|id| id)
-                .collect::<Vec<Uuid>>()
+}
+
+impl Handler<GetSessionLog> for SessionManager {
+    type Result = ResponseFuture<Result<Vec<LogEntry>, SessionManagerError>>;
+
+    fn handle(&mut self, msg: GetSessionLog, _ctx: &mut Self::Context) -> Self::Result {
+        let session = match self.sessions.get(&msg.session_id) {
+            Some(entry) if entry.host_id != msg.host_id => Err(SessionManagerError::NotHost),
+            Some(entry) => Ok(entry.addr.clone()),
+            None => Err(SessionManagerError::SessionNotFound),
+        };
+        Box::pin(async move {
+            let session = session?;
+            session
+                .send(GetLog)
+                .await
+                .map_err(|_| SessionManagerError::SessionNotFound)
         })
-        .await
-        .unwrap();
+    }
+}
+
+impl Handler<SessionActivity> for SessionManager {
+    type Result = ();
 
-        let ret_ids = ids.clone();
-        ret_ids
+    fn handle(&mut self, msg: SessionActivity, _ctx: &mut Self::Context) -> Self::Result {
+        if let Some(entry) = self.sessions.get_mut(&msg.id) {
+            entry.last_seen = Instant::now();
+            entry.host_id = msg.host_id;
+            entry.state = msg.state;
+            entry.players = msg.players;
+        }
     }
 }
 
@@ -144,31 +222,7 @@ impl Handler<CloseSession> for SessionManager {
     type Result = ();
 
     fn handle(&mut self, msg: CloseSession, _ctx: &mut Self::Context) -> Self::Result {
-        let sessions = self.sessions.clone();
-        let msg_id = msg.0;
-
-        spawn(async move {
-            let session_ids: Vec<Option<Uuid>> = {
-                let sessions = sessions.lock().unwrap();
-                let futures = sessions.iter().map(|session| {
-                    let session = session.clone();
-                    async move {
-                        let session_id_res = session.send(GetSessionId()).await.ok();
-
-                        session_id_res.and_then(|id| Uuid::parse_str(&id).ok())
-                    }
-                });
-                join_all(futures).await
-            };
-
-            let mut sessions = sessions.lock().unwrap();
-            sessions.retain(|_session| {
-                if let Some(session_id) = session_ids.iter().find(|id| id.is_some()) {
-                    *session_id.as_ref().unwrap() != msg_id
-                } else {
-                    true
-                }
-            });
-        });
+        log::info!("Closing session: {:#?}", msg.0);
+        self.close_session(msg.0);
     }
 }
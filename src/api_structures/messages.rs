@@ -1,13 +1,15 @@
 use actix::{Addr, Message};
 use uuid::Uuid;
-use crate::api_structures::managers::game_manager::GameBundle;
+use crate::api_structures::game_log::LogEntry;
 use super::{
     id::{ SessionId, UserId},
-    packet_parser::{Packet, PacketResponse},
+    packet_parser::{Packet, PacketResponse, ServerEvent},
     session::Session,
 };
 use crate::api_structures::packet_parser::PacketError;
-use crate::api_structures::session::SessionError;
+use crate::api_structures::card_game::deck::DeckBundle;
+use crate::api_structures::managers::session_manager::SessionManagerError;
+use crate::api_structures::session::{SessionCode, SessionError, SessionState};
 use crate::api_structures::session_connection::SessionConnection;
 #[derive(Message, Debug)]
 #[rtype(result = "()")]
@@ -45,7 +47,11 @@ pub struct BroadcastMessage(pub String);
 
 #[derive(Message, Debug)]
 #[rtype(result = "()")]
-pub struct AddConnection(pub Addr<SessionConnection>);
+pub struct AddConnection(pub UserId, pub Addr<SessionConnection>);
+
+#[derive(Message, Debug)]
+#[rtype(result = "()")]
+pub struct ConnectionClosed(pub UserId, pub Addr<SessionConnection>);
 
 
 
@@ -57,9 +63,22 @@ pub struct SendToClient(pub String);
 #[rtype(result = "()")]
 pub struct ConnectWithSession(pub Addr<SessionConnection>);
 
+/// Something the server tells a client without being asked.
+#[derive(Message, Debug)]
+#[rtype(result = "()")]
+pub struct PushEvent(pub ServerEvent);
+
+/// Packet sent by a client, `sender` is the user the connection belongs to.
 #[derive(Message, Debug)]
 #[rtype(result = "Result<PacketResponse, PacketError>")]
-pub struct SendPacket(pub Packet);
+pub struct ClientPacket {
+    pub sender: UserId,
+    pub packet: Packet,
+}
+
+#[derive(Message, Debug)]
+#[rtype(result = "Vec<LogEntry>")]
+pub struct GetLog;
 
 #[derive(Message, Debug)]
 #[rtype(result = "()")]
@@ -69,9 +88,54 @@ pub struct CloseSessionConnection;
 #[rtype(result = "()")]
 pub struct CloseSession(pub Uuid);
 
+#[derive(Message, Debug)]
+#[rtype(result = "Result<(SessionId, SessionCode, u64), SessionManagerError>")]
+pub struct InitSession {
+    pub host_id: UserId,
+    pub username: String,
+    /// Bundled by the caller, so validating a deck never holds up the manager.
+    pub deck: DeckBundle,
+    pub seed: Option<u64>,
+}
+
+#[derive(Message, Debug)]
+#[rtype(result = "Result<SessionConnection, SessionManagerError>")]
+pub struct JoinSession {
+    pub session_id: SessionId,
+    pub user_id: UserId,
+    pub username: String,
+}
+
+#[derive(Message, Debug)]
+#[rtype(result = "Option<SessionId>")]
+pub struct UnwrapCode(pub SessionCode);
+
+#[derive(Message, Debug)]
+#[rtype(result = "Vec<SessionId>")]
+pub struct ListSessions;
+
+#[derive(Message, Debug)]
+#[rtype(result = "Result<Vec<LogEntry>, SessionManagerError>")]
+pub struct GetSessionLog {
+    pub session_id: SessionId,
+    /// Only the host gets the log, its first entry carries the seed every draw follows from.
+    pub host_id: UserId,
+}
+
+/// Sent by a session whenever a client does something, keeps it away from the idle reaper.
+#[derive(Message, Debug)]
+#[rtype(result = "()")]
+pub struct SessionActivity {
+    pub id: SessionId,
+    pub host_id: UserId,
+    pub state: SessionState,
+    pub players: usize,
+}
+
+/// Tells a session to disconnect everyone and stop.
 #[derive(Message, Debug)]
 #[rtype(result = "()")]
-pub struct PlayerUpdate(pub Vec<String>, pub GameBundle);
+pub struct Shutdown;
 
 #[derive(Message, Debug)]
 #[rtype(result = "()")]
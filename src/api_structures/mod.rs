@@ -1,5 +1,7 @@
 pub mod api_state;
 pub mod card_game;
+pub mod config;
+pub mod game_log;
 pub mod id;
 pub mod managers;
 pub mod messages;
@@ -2,10 +2,8 @@ use actix::MailboxError;
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 use crate::api_structures::managers::game_manager::{CardResult, GameBundle};
-use super::{
-    card_game::deck::Deck,
-    managers::game_manager::CardOption,
-};
+use super::card_game::{deck::Deck, validation::DeckReport};
+use super::session::SessionState;
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct Table {
@@ -25,36 +23,108 @@ pub struct State {
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(tag = "error")]
 pub enum PacketError {
-    CipaChuj,
-    GameManagerError,
-    Errorito,
-    CantChangeDeck,
-    #[serde(skip)]
-    MailboxError(MailboxError),
+    // protocol
+    InvalidPacket { reason: String, raw_excerpt: String },
+    UnsupportedPacket { packet: String },
+
+    // session
+    /// The session actor is gone or its mailbox is closed.
+    SessionUnavailable { reason: String },
+    NotHost,
+    WrongTurn,
+    InvalidState { state: SessionState },
+    NotEnoughPlayers { required: usize, present: usize },
+    TooManyPlayers { allowed: usize, present: usize },
+
+    // game
+    UnknownOption { option: Uuid },
+    DeckInvalid { report: DeckReport },
+}
+
+/// Picked by the client, echoed untouched on whatever answers its packet.
+pub type RequestId = u64;
+
+/// What clients receive, the variant's fields plus a code that never changes and an english message.
+#[derive(Serialize, Debug)]
+pub struct PacketErrorFrame<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<RequestId>,
+    #[serde(flatten)]
+    pub error: &'a PacketError,
+    pub code: u16,
+    pub message: String,
+}
+
+impl PacketError {
+    /// Stable across releases, clients key their translations on it.
+    pub fn code(&self) -> u16 {
+        match self {
+            PacketError::InvalidPacket { .. } => 1000,
+            PacketError::UnsupportedPacket { .. } => 1001,
+            PacketError::SessionUnavailable { .. } => 2000,
+            PacketError::NotHost => 2001,
+            PacketError::WrongTurn => 2002,
+            PacketError::InvalidState { .. } => 2003,
+            PacketError::NotEnoughPlayers { .. } => 2004,
+            PacketError::TooManyPlayers { .. } => 2005,
+            PacketError::UnknownOption { .. } => 3000,
+            PacketError::DeckInvalid { .. } => 3001,
+        }
+    }
+
+    pub fn message(&self) -> String {
+        match self {
+            PacketError::InvalidPacket { reason, .. } => format!("Malformed packet: {}", reason),
+            PacketError::UnsupportedPacket { packet } => format!("The server doesn't accept {} packets", packet),
+            PacketError::SessionUnavailable { .. } => "The session is no longer available".to_string(),
+            PacketError::NotHost => "Only the host can do that".to_string(),
+            PacketError::WrongTurn => "It's not your turn".to_string(),
+            PacketError::InvalidState { state } => format!("Not allowed while the session is in {:?}", state),
+            PacketError::NotEnoughPlayers { required, present } => {
+                format!("This deck needs at least {} players, {} joined", required, present)
+            }
+            PacketError::TooManyPlayers { allowed, present } => {
+                format!("This deck allows at most {} players, {} joined", allowed, present)
+            }
+            PacketError::UnknownOption { .. } => "That option isn't available anymore".to_string(),
+            PacketError::DeckInvalid { report } => {
+                format!("The deck has {} error(s)", report.errors.len())
+            }
+        }
+    }
+
+    pub fn frame(&self, request_id: Option<RequestId>) -> PacketErrorFrame<'_> {
+        PacketErrorFrame {
+            request_id,
+            error: self,
+            code: self.code(),
+            message: self.message(),
+        }
+    }
 }
 
 impl From<MailboxError> for PacketError {
     fn from(value: MailboxError) -> Self {
-        PacketError::MailboxError(value)
+        PacketError::SessionUnavailable { reason: value.to_string() }
     }
 }
 
+/// A packet as the client sends it, `request_id` is optional and only used for correlation.
+#[derive(Deserialize, Debug)]
+pub struct Request {
+    #[serde(default)]
+    pub request_id: Option<RequestId>,
+    #[serde(flatten)]
+    pub packet: Packet,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(tag = "packet")]
 pub enum Packet {
-    // API -> CLIENT
-    UpdateState {
-        new_state: GameBundle,
-    },
-    PlayersUpdate {},
-    CardResult { card: CardResult, #[serde(skip)] bundle: GameBundle },
-    FinishGame {},
-
     // API <-> CLIENT
     AdminToken {
         token: Uuid,
     },
-    TestError {},
     TestPacketWithString {
         string: String,
     },
@@ -62,7 +132,7 @@ pub enum Packet {
     // API <- CLIENT
     StartGame {},
     SetDeck {
-        deck: Deck,
+        deck: Box<Deck>,
     },
     PlayerLeft {
         id: Uuid,
@@ -71,54 +141,90 @@ pub enum Packet {
         chosen: Uuid,
     },
     PlayerDone {},
+    FinishGame {},
     CloseSession {},
     GetPlayers {},
 }
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(tag = "packet")]
+#[allow(clippy::enum_variant_names)] // the names are the `packet` tags clients match on
 pub enum PacketResponse {
     // API -> CLIENT
-    UpdateStateOk { bundle: GameBundle },
     CardResultOk { card: CardResult, #[serde(skip)] bundle: GameBundle },
     FinishGameOk,
 
     // API <-> CLIENT
     AdminTokenOk,
     TestPacketWithStringOk { string: String },
-    Unit,
     StartGameOk,
     // API <- CLIENT
     SetDeckOk { #[serde(skip)] bundle: GameBundle },
     PlayerLeftOk { #[serde(skip)] bundle: GameBundle },
-    PlayerDoneChoiseOk { #[serde(skip)] bundle: GameBundle },
     CloseSessionOk,
-    PlayerDoneOk { #[serde(skip)] bundle: GameBundle },
     GetPlayersOk { players: Vec<String>,  #[serde(skip)] bundle: GameBundle },
-    PlayersUpdateOk { players: Vec<String>, #[serde(skip)] bundle: GameBundle },
+}
+
+/// A reply on the wire, carries the `request_id` of the packet it answers.
+#[derive(Serialize, Debug)]
+pub struct ResponseFrame<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<RequestId>,
+    #[serde(flatten)]
+    pub response: &'a PacketResponse,
+}
+
+/// Sent by the server on its own, never answers a packet so it has no `request_id`.
+/// Tagged with `event` instead of `packet` so clients can tell the two apart.
+#[derive(Serialize, Debug, Clone)]
+#[serde(tag = "event")]
+pub enum ServerEvent {
+    PlayersUpdate { players: Vec<String>, disconnected: Vec<String>, bundle: GameBundle },
+    GameStarted { bundle: GameBundle },
+    CardResult { card: CardResult, bundle: GameBundle },
+    StateUpdate { bundle: GameBundle },
+    GameFinished,
+}
+
+impl Packet {
+    /// The `packet` tag as it appears on the wire.
+    pub fn name(&self) -> String {
+        serde_json::to_value(self)
+            .ok()
+            .and_then(|value| value["packet"].as_str().map(str::to_string))
+            .unwrap_or_default()
+    }
 }
 
 impl PacketResponse {
+    pub fn frame(&self, request_id: Option<RequestId>) -> ResponseFrame<'_> {
+        ResponseFrame { request_id, response: self }
+    }
+
     pub fn get_bundle(&self) -> Option<GameBundle> {
         match self {
             PacketResponse::SetDeckOk { bundle } => Some(bundle.clone()),
             PacketResponse::PlayerLeftOk { bundle } => Some(bundle.clone()),
-            PacketResponse::PlayerDoneChoiseOk { bundle } => Some(bundle.clone()),
-            PacketResponse::PlayerDoneOk { bundle } => Some(bundle.clone()),
             PacketResponse::GetPlayersOk { bundle, .. } => Some(bundle.clone()),
-            PacketResponse::PlayersUpdateOk { bundle, .. } => Some(bundle.clone()),
-            PacketResponse::UpdateStateOk { bundle } => Some(bundle.clone()),
             PacketResponse::CardResultOk { bundle, .. } => Some(bundle.clone()),
             _ => None,
         }
     }
 }
 
-pub fn deserialize_json(json: &str) -> Packet {
-    let packet: Packet = serde_json::from_str(json).unwrap();
-    packet
-}
+const RAW_EXCERPT_LEN: usize = 64;
 
-#[cfg(test)]
+pub fn deserialize_json(json: &str) -> Result<Request, PacketError> {
+    serde_json::from_str(json).map_err(|err| PacketError::InvalidPacket {
+        reason: err.to_string(),
+        raw_excerpt: json.chars().take(RAW_EXCERPT_LEN).collect(),
+    })
+}
 
-fn test_serialization() {}
+/// Best effort, lets the error for a malformed packet still reach the right request.
+pub fn peek_request_id(json: &str) -> Option<RequestId> {
+    serde_json::from_str::<serde_json::Value>(json)
+        .ok()?
+        .get("request_id")?
+        .as_u64()
+}
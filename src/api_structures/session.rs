@@ -1,16 +1,20 @@
 use std::cell::{Cell, RefCell};
 use std::rc::Rc;
 use super::managers::session_manager::SessionManager;
-use super::messages::{AddConnection, AddPlayer, CloseSession, CloseSessionConnection, Dump, GetHostId, GetSessionId, PlayerUpdate, SendPacket, SendToClient, VerifyExistence};
-use super::packet_parser::{Packet, PacketError, PacketResponse};
+use super::messages::{AddConnection, AddPlayer, ClientPacket, ConnectionClosed, CloseSession, CloseSessionConnection, Dump, GetHostId, GetLog, GetSessionId, PushEvent, SendToClient, SessionActivity, Shutdown, VerifyExistence};
+use super::packet_parser::{Packet, PacketError, PacketResponse, ServerEvent};
 use super::session_connection::SessionConnection;
+use crate::api_structures::config;
+use crate::api_structures::game_log::{GameEvent, GameLog, LogEntry};
 use crate::api_structures::id::*;
-use crate::api_structures::managers::game_manager::GameManager;
+use crate::api_structures::managers::game_manager::{CardResult, GameManager};
 use crate::api_structures::messages::BroadcastMessage;
 use crate::api_structures::messages::TestMessage;
-use actix::{Actor, Addr, Context, Handler};
-use chrono::format::Item;
+use actix::{Actor, ActorContext, Addr, ArbiterHandle, AsyncContext, Context, Handler, SpawnHandle};
+use std::collections::HashMap;
+use std::time::Duration;
 use rand::prelude::*;
+use rand::rngs::StdRng;
 
 use serde::{Deserialize, Serialize};
 
@@ -29,24 +33,28 @@ fn generate_random_string(length: usize) -> String {
 }
 
 use uuid::Uuid;
-use crate::api_structures::card_game::deck::{Deck, Selector};
-use crate::api_structures::packet_parser::Packet::StartGame;
+use crate::api_structures::card_game::deck::{DeckBundle, Selector};
 use crate::api_structures::session::SessionState::Game;
 
-#[derive(Deserialize, Serialize, Clone, Debug)]
+/// Every random choice in a session goes through this rng, so a game can be replayed from its seed.
+pub type SessionRng = Rc<RefCell<StdRng>>;
+
+#[derive(Serialize, Clone, Debug)]
 pub struct Players {
     pub players: Vec<Player>,
     pub idx: Cell<usize>,
+    #[serde(skip)]
+    rng: SessionRng,
 }
 
 
 impl Players {
-    pub fn new() -> Self {
-        Self { players: Vec::new(), idx: Cell::new(0)  }
+    pub fn new(rng: SessionRng) -> Self {
+        Self { players: Vec::new(), idx: Cell::new(0), rng }
     }
     pub fn add_player(&mut self, player: Player) {
         self.players.push(player);
-        self.players.shuffle(&mut thread_rng());
+        self.players.shuffle(&mut *self.rng.borrow_mut());
     }
 
     pub fn remove_player(&mut self, id: UserId) {
@@ -56,26 +64,30 @@ impl Players {
         self.players.iter().map(|x| x.username.clone()).collect()
     }
 
+    pub fn get_disconnected(&self) -> Vec<String> {
+        self.players.iter().filter(|x| !x.connected).map(|x| x.username.clone()).collect()
+    }
+
     pub fn consume(&self) {
         self.idx.set(self.idx.get() + 1);
     }
 
     pub fn get_player(&self, selector: Selector) -> &Player {
         match selector {
-            Selector::Current => &self.players[self.idx.get()],
+            Selector::Current => &self.players[self.idx.get() % self.players.len()],
             Selector::Next => &self.players[(self.idx.get() + 1) % self.players.len()],
-            Selector::Previous => &self.players[(self.idx.get() - 1) % self.players.len()],
-            Selector::Random => &self.players[thread_rng().gen_range(0..self.players.len())],
+            Selector::Previous => &self.players[(self.idx.get() + self.players.len() - 1) % self.players.len()],
+            Selector::Random => &self.players[self.rng.borrow_mut().gen_range(0..self.players.len())],
             Selector::None => self.players.first().unwrap(),
         }
     }
 
     pub fn clone_player(&self, selector: Selector) -> Player {
         match selector {
-            Selector::Current => self.players[self.idx.get()].clone(),
+            Selector::Current => self.players[self.idx.get() % self.players.len()].clone(),
             Selector::Next => self.players[(self.idx.get()  + 1) % self.players.len()].clone(),
-            Selector::Previous => self.players[(self.idx.get() - 1) % self.players.len()].clone(),
-            Selector::Random => self.players[thread_rng().gen_range(0..self.players.len())].clone(),
+            Selector::Previous => self.players[(self.idx.get() + self.players.len() - 1) % self.players.len()].clone(),
+            Selector::Random => self.players[self.rng.borrow_mut().gen_range(0..self.players.len())].clone(),
             Selector::None => self.players.first().unwrap().clone(),
         }
     }
@@ -84,16 +96,37 @@ impl Players {
 #[derive(Clone, Debug)]
 pub struct Connections {
     pub connections: Vec<Addr<SessionConnection>>,
+    owners: HashMap<Addr<SessionConnection>, UserId>,
 }
 
 impl Connections {
     pub fn new() -> Self {
-        Self { connections: Vec::new() }
+        Self { connections: Vec::new(), owners: HashMap::new() }
     }
 
-    pub fn add_connection(&mut self, connection: Addr<SessionConnection>) {
+    pub fn add_connection(&mut self, user_id: UserId, connection: Addr<SessionConnection>) {
+        self.owners.insert(connection.clone(), user_id);
         self.connections.push(connection);
     }
+
+    pub fn remove_connection(&mut self, connection: &Addr<SessionConnection>) -> Option<UserId> {
+        self.connections.retain(|conn| conn != connection);
+        self.owners.remove(connection)
+    }
+
+    /// Every connection not owned by `user_id`, the sender already gets the result as its reply.
+    pub fn except(&self, user_id: UserId) -> Vec<Addr<SessionConnection>> {
+        self.connections
+            .iter()
+            .filter(|conn| self.owners.get(*conn) != Some(&user_id))
+            .cloned()
+            .collect()
+    }
+
+    /// A player may briefly have two sockets while reconnecting, they're only gone once both are.
+    pub fn is_connected(&self, user_id: UserId) -> bool {
+        self.owners.values().any(|owner| *owner == user_id)
+    }
 }
 
 #[derive(Deserialize, Serialize, Clone, Debug)]
@@ -101,6 +134,8 @@ pub struct Player {
     pub username: String,
     pub id: UserId,
     is_host: bool,
+    #[serde(skip)]
+    pub connected: bool,
 }
 
 impl Player {
@@ -109,13 +144,15 @@ impl Player {
             id,
             username,
             is_host,
+            connected: true,
         }
     }
 }
 
 #[derive(Deserialize, Serialize, Clone, Debug)]
 pub enum SessionError {
-    CantJoinActiveGame
+    CantJoinActiveGame,
+    SessionFull,
 }
 
 
@@ -159,6 +196,12 @@ pub struct Session {
     pub session_state: SessionState,
     pub manager_addr: Addr<SessionManager>,
     pub code: SessionCode,
+    pub log: GameLog,
+    pub last_card: Option<CardResult>,
+    /// Player the card on the table was drawn for.
+    pub turn: Option<UserId>,
+    pub grace_period: Duration,
+    pending_removals: HashMap<UserId, SpawnHandle>,
 }
 
 impl Actor for Session {
@@ -166,32 +209,150 @@ impl Actor for Session {
 }
 
 impl Session {
-    pub async fn init(
+    /// Starts the session on `arbiter`, the `Rc` state is built there since it can't cross threads.
+    pub fn init(
+        arbiter: &ArbiterHandle,
         host_id: UserId,
         _username: String,
         manager_addr: Addr<SessionManager>,
         code: SessionCode,
-        deck: Deck,
+        bundle: DeckBundle,
+        seed: u64,
     ) -> (Addr<Self>, SessionId) {
         let id = Uuid::new_v4();
-        let plrs = Rc::new(RefCell::new(Players::new()));
-        let plrs_clone = Rc::clone(&plrs);
 
-        let addr = Self {
-            id,
-            host_id,
-            connections: Connections::new(),
-            players: plrs,
-            admin_token: Uuid::new_v4(),
-            game_manager: GameManager::init(deck.into_bundle(), plrs_clone),
-            session_state: SessionState::Lobby,
-            manager_addr,
-            code,
-        }
-          .start();
+        let addr = Session::start_in_arbiter(arbiter, move |_ctx| {
+            let rng: SessionRng = Rc::new(RefCell::new(StdRng::seed_from_u64(seed)));
+            let plrs = Rc::new(RefCell::new(Players::new(rng.clone())));
+            let plrs_clone = Rc::clone(&plrs);
+
+            Self {
+                id,
+                host_id,
+                connections: Connections::new(),
+                players: plrs,
+                admin_token: Uuid::new_v4(),
+                log: GameLog::new(seed, bundle.clone()),
+                last_card: None,
+                turn: None,
+                grace_period: config::disconnect_grace_period(),
+                pending_removals: HashMap::new(),
+                game_manager: GameManager::init(bundle, plrs_clone, rng),
+                session_state: SessionState::Lobby,
+                manager_addr,
+                code,
+            }
+        });
 
         (addr, id)
     }
+
+    pub fn finish_game(&mut self) {
+        log::info!("Finishing game: {:#?}", self.id);
+        self.session_state = SessionState::Lobby;
+        self.game_manager.reset_game_state();
+        self.last_card = None;
+        self.turn = None;
+        self.log.push(GameEvent::GameFinished);
+
+        for conn in self.connections.connections.clone() {
+            conn.do_send(PushEvent(ServerEvent::GameFinished));
+        }
+    }
+
+    fn report_activity(&self) {
+        self.manager_addr.do_send(SessionActivity {
+            id: self.id,
+            host_id: self.host_id,
+            state: self.session_state.clone(),
+            players: self.players.borrow().players.len(),
+        });
+    }
+
+    fn broadcast_players(&self) {
+        let players = self.players.borrow();
+        let event = ServerEvent::PlayersUpdate {
+            players: players.get_players(),
+            disconnected: players.get_disconnected(),
+            bundle: self.game_manager.bundle_state(),
+        };
+        for conn in &self.connections.connections {
+            conn.do_send(PushEvent(event.clone()));
+        }
+    }
+
+    /// Drops the player from the game, closes the session once nobody is left.
+    fn remove_player(&mut self, id: UserId) -> PacketResponse {
+        self.log.push(GameEvent::PlayerLeft { id });
+        self.game_manager.remove_player(id);
+
+        { // scoping for ref mut drop
+            let mut mut_ref = self.players.borrow_mut();
+            mut_ref.players.retain(|x| x.id != id);
+        }
+
+        let players = self.players.borrow();
+        self.game_manager.regen_states(&players);
+        if players.players.is_empty() {
+            self.session_state = SessionState::Lobby;
+
+            for conn in &self.connections.connections {
+                conn.do_send(CloseSessionConnection);
+            }
+            self.manager_addr.do_send(CloseSession(self.id));
+            PacketResponse::CloseSessionOk
+        } else {
+            // only the host leaving hands the session over, anyone else keeps it where it is
+            if id == self.host_id {
+                self.host_id = players.players[0].id;
+            }
+            PacketResponse::PlayerLeftOk { bundle: self.game_manager.bundle_state() }
+        }
+    }
+
+    /// A new deck has to fit everyone already at the table, `min_players` is only checked on `StartGame`.
+    fn check_max_players(&self, bundle: &DeckBundle) -> Result<(), PacketError> {
+        let present = self.players.borrow().players.len();
+        if bundle.max_players > 0 && present > bundle.max_players as usize {
+            return Err(PacketError::TooManyPlayers { allowed: bundle.max_players as usize, present });
+        }
+        Ok(())
+    }
+
+    fn check_host(&self, sender: UserId) -> Result<(), PacketError> {
+        if sender == self.host_id {
+            Ok(())
+        } else {
+            Err(PacketError::NotHost)
+        }
+    }
+
+    /// Cards are answered by the player they were drawn for, the host may answer for anyone.
+    fn check_turn(&self, sender: UserId) -> Result<(), PacketError> {
+        match self.session_state {
+            SessionState::PreGame | SessionState::Game => match self.turn {
+                Some(player) if player != sender && sender != self.host_id => Err(PacketError::WrongTurn),
+                _ => Ok(()),
+            },
+            _ => Err(PacketError::InvalidState { state: self.session_state.clone() }),
+        }
+    }
+
+    fn draw_card(&mut self) -> Option<CardResult> {
+        let card = self.game_manager.get_next_card()?;
+        self.log.push(GameEvent::CardDrawn {
+            card: self.game_manager.current_card(),
+            text: card.text.clone(),
+            options: card.state_options.clone(),
+        });
+        self.session_state = SessionState::Game;
+        self.last_card = Some(card.clone());
+        // drawing already moved the turn on, the card belongs to whoever was current before that
+        let players = self.players.borrow();
+        self.turn = (!players.players.is_empty()).then(|| players.get_player(Selector::Previous).id);
+        drop(players);
+        Some(card)
+    }
 }
 
 impl Handler<TestMessage> for Session {
@@ -217,28 +378,40 @@ impl Handler<AddPlayer> for Session {
     type Result = Result<SessionConnection, SessionError>;
     fn handle(&mut self, msg: AddPlayer, _ctx: &mut Self::Context) -> Self::Result {
 
+        // a known player is reconnecting, keep their seat and catch the new connection up
+        let returning = self.players.borrow().players.iter().find(|p| p.id == msg.id).cloned();
+        if let Some(player) = returning {
+            log::info!("Player {:#?} reconnected to session: {:#?}", player.id, self.id);
+            return Ok(SessionConnection::new(player.id, msg.session_addr, player.is_host)
+                .resume(self.game_manager.bundle_state(), self.last_card.clone()));
+        }
+
         if let Game = self.session_state {
             return Err(SessionError::CantJoinActiveGame);
         }
 
+        if !self.game_manager.has_room(self.players.borrow().players.len()) {
+            return Err(SessionError::SessionFull);
+        }
+
         log::info!("Adding player: {:#?} to session: {:#?}", msg, self.id);
 
         { // scoping for mutable players drop
             let mut players = self.players.borrow_mut();
-            let player = Player::new(msg.id, msg.username, msg.is_host);
+            let player = Player::new(msg.id, msg.username.clone(), msg.is_host);
             if players.players.is_empty() {
                 self.session_state = SessionState::Lobby;
             }
             players.add_player(player);
         }
+        self.log.push(GameEvent::PlayerJoined { id: msg.id, username: msg.username, is_host: msg.is_host });
 
         self.game_manager.regen();
 
         let connection = SessionConnection::new(msg.id, msg.session_addr, msg.is_host);
 
-        for conn in &self.connections.connections {
-            conn.do_send(PlayerUpdate(self.players.borrow().get_players(), self.game_manager.bundle_state()));
-        }
+        self.broadcast_players();
+        self.report_activity();
 
         Ok(connection)
     }
@@ -263,117 +436,211 @@ impl Handler<BroadcastMessage> for Session {
 impl Handler<AddConnection> for Session {
     type Result = ();
 
-    fn handle(&mut self, msg: AddConnection, _ctx: &mut Self::Context) -> Self::Result {
-        log::info!("Adding connection: {:#?}", msg.0);
-        self.connections.add_connection(msg.0);
+    fn handle(&mut self, msg: AddConnection, ctx: &mut Self::Context) -> Self::Result {
+        log::info!("Adding connection: {:#?}", msg.1);
+        self.connections.add_connection(msg.0, msg.1);
+
+        if let Some(handle) = self.pending_removals.remove(&msg.0) {
+            ctx.cancel_future(handle);
+        }
+        let reconnected = match self.players.borrow_mut().players.iter_mut().find(|p| p.id == msg.0) {
+            Some(player) if !player.connected => {
+                player.connected = true;
+                true
+            }
+            _ => false,
+        };
+        if reconnected {
+            self.broadcast_players();
+        }
     }
 }
 
+impl Handler<Shutdown> for Session {
+    type Result = ();
 
+    fn handle(&mut self, _msg: Shutdown, ctx: &mut Self::Context) -> Self::Result {
+        log::info!("Shutting down session: {:#?}", self.id);
+        for conn in &self.connections.connections {
+            conn.do_send(CloseSessionConnection);
+        }
+        ctx.stop();
+    }
+}
 
-impl Handler<SendPacket> for Session {
-    type Result = Result<PacketResponse, PacketError>;
+impl Handler<ConnectionClosed> for Session {
+    type Result = ();
+
+    fn handle(&mut self, msg: ConnectionClosed, ctx: &mut Self::Context) -> Self::Result {
+        let ConnectionClosed(user_id, addr) = msg;
+        self.connections.remove_connection(&addr);
+        if self.connections.is_connected(user_id) {
+            return;
+        }
+
+        let disconnected = match self.players.borrow_mut().players.iter_mut().find(|p| p.id == user_id) {
+            Some(player) => {
+                player.connected = false;
+                true
+            }
+            None => false,
+        };
+        if !disconnected {
+            return;
+        }
 
-    fn handle(&mut self, msg: SendPacket, _ctx: &mut Self::Context) -> Self::Result {
-        match msg.0 {
-            Packet::TestError {  } => {
-                Err(PacketError::CipaChuj)
+        log::info!("Player {:#?} disconnected from session: {:#?}", user_id, self.id);
+        self.broadcast_players();
+
+        let handle = ctx.run_later(self.grace_period, move |session, _ctx| {
+            session.pending_removals.remove(&user_id);
+            log::info!("Removing player {:#?} after grace period", user_id);
+            if let PacketResponse::PlayerLeftOk { .. } = session.remove_player(user_id) {
+                session.broadcast_players();
+                session.report_activity();
             }
+        });
+        if let Some(old) = self.pending_removals.insert(user_id, handle) {
+            ctx.cancel_future(old);
+        }
+    }
+}
+
+
+
+impl Handler<GetLog> for Session {
+    type Result = Vec<LogEntry>;
+
+    fn handle(&mut self, _msg: GetLog, _ctx: &mut Self::Context) -> Self::Result {
+        self.log.entries.clone()
+    }
+}
+
+impl Handler<ClientPacket> for Session {
+    type Result = Result<PacketResponse, PacketError>;
+
+    fn handle(&mut self, msg: ClientPacket, ctx: &mut Self::Context) -> Self::Result {
+        let result = self.handle_packet(msg, ctx);
+        // reported after the packet so the manager sees the state it left the session in
+        self.report_activity();
+        result
+    }
+}
+
+impl Session {
+    fn handle_packet(&mut self, msg: ClientPacket, ctx: &mut Context<Self>) -> Result<PacketResponse, PacketError> {
+        match msg.packet {
             Packet::TestPacketWithString { string } => {
                 Ok(PacketResponse::TestPacketWithStringOk { string })
             }
             Packet::SetDeck { deck } => {
                 log::info!("Setting deck: {:#?}", deck);
+                self.check_host(msg.sender)?;
                 if let Game = self.session_state {
-                    return Err(PacketError::CantChangeDeck);
+                    return Err(PacketError::InvalidState { state: Game });
                 }
-                self.game_manager.change_deck(deck.into_bundle());
+                let bundle = deck
+                    .into_bundle()
+                    .map_err(|report| PacketError::DeckInvalid { report })?;
+                self.check_max_players(&bundle)?;
+                self.log.push(GameEvent::DeckChanged { deck: bundle.clone() });
+                self.game_manager.change_deck(bundle);
                 Ok(PacketResponse::SetDeckOk { bundle: self.game_manager.bundle_state() })
             }
             Packet::GetPlayers {  } => {
                 Ok(PacketResponse::GetPlayersOk { players: self.players.borrow().get_players(), bundle: self.game_manager.bundle_state() })
             }
             Packet::PlayerLeft { id } => {
-                self.game_manager.remove_player(id);
-
-                { // scoping for ref mut drop
-                    let mut mut_ref = self.players.borrow_mut();
-                    mut_ref.players.retain(|x| x.id != id);
+                if id != msg.sender {
+                    self.check_host(msg.sender)?;
                 }
-
-                let players = self.players.borrow();
-                self.game_manager.regen_states(&players);
-                if self.players.borrow().players.is_empty() {
-                    self.session_state = SessionState::Lobby;
-
-                    for conn in &self.connections.connections {
-                        conn.do_send(CloseSessionConnection);
-                    }
-                    self.manager_addr.do_send(CloseSession(self.id.clone()));
-                    Ok(PacketResponse::CloseSessionOk)
-                } else {
-                    self.host_id = self.players.borrow().players[0].id;
-                    Ok(PacketResponse::PlayerLeftOk { bundle: self.game_manager.bundle_state() })
+                if let Some(handle) = self.pending_removals.remove(&id) {
+                    ctx.cancel_future(handle);
                 }
+                Ok(self.remove_player(id))
             }
             Packet::PlayerDoneChoise { chosen } => {
                 log::info!("Player done choise: {:#?}", chosen);
-                self.game_manager.resolve_state(chosen);
-                let card = self.game_manager.get_next_card().unwrap();
+                self.check_turn(msg.sender)?;
+                let updates = self
+                    .game_manager
+                    .resolve_state(chosen)
+                    .ok_or(PacketError::UnknownOption { option: chosen })?;
+                self.log.push(GameEvent::OptionChosen { option: chosen, by: Some(msg.sender) });
+                self.log.push(GameEvent::StateChanged { updates });
+                if self.game_manager.bundle_state().is_game_over() {
+                    self.finish_game();
+                    return Ok(PacketResponse::FinishGameOk);
+                }
+                let Some(card) = self.draw_card() else {
+                    self.finish_game();
+                    return Ok(PacketResponse::FinishGameOk);
+                };
                 let bundle = self.game_manager.bundle_state();
 
-                for conn in self.connections.connections.clone() {
-                    conn.do_send(SendPacket(Packet::CardResult { card: card.clone(), bundle: bundle.clone() }));
+                for conn in self.connections.except(msg.sender) {
+                    conn.do_send(PushEvent(ServerEvent::CardResult { card: card.clone(), bundle: bundle.clone() }));
                 }
 
                 Ok(PacketResponse::CardResultOk { card: card.clone(), bundle: bundle.clone() })
             }
             Packet::PlayerDone { .. } => {
                 log::info!("Player done");
-                let card = self.game_manager.get_next_card().unwrap();
+                self.check_turn(msg.sender)?;
+                let Some(card) = self.draw_card() else {
+                    self.finish_game();
+                    return Ok(PacketResponse::FinishGameOk);
+                };
                 let bundle = self.game_manager.bundle_state();
 
-                for conn in self.connections.connections.clone() {
-                    conn.do_send(SendPacket(Packet::CardResult { card: card.clone(), bundle: bundle.clone() }));
+                for conn in self.connections.except(msg.sender) {
+                    conn.do_send(PushEvent(ServerEvent::CardResult { card: card.clone(), bundle: bundle.clone() }));
                 }
 
                 Ok(PacketResponse::CardResultOk { card: card.clone(), bundle: bundle.clone() })
             }
             Packet::CloseSession { .. } => {
                 log::info!("Closing session: {:#?}", self.id);
+                self.check_host(msg.sender)?;
                 for conn in self.connections.connections.clone() {
                     conn.do_send(CloseSessionConnection);
                 }
-                self.manager_addr.do_send(CloseSession(self.id.clone()));
+                self.manager_addr.do_send(CloseSession(self.id));
                 Ok(PacketResponse::CloseSessionOk)
             }
             
             Packet::StartGame { } => {
                 log::info!("Starting game: {:#?}", self.id);
+                self.check_host(msg.sender)?;
+                if !matches!(self.session_state, SessionState::Lobby) {
+                    return Err(PacketError::InvalidState { state: self.session_state.clone() });
+                }
+                let present = self.players.borrow().players.len();
+                let required = self.game_manager.min_players();
+                if present < required {
+                    return Err(PacketError::NotEnoughPlayers { required, present });
+                }
+
                 self.session_state = SessionState::PreGame;
                 self.game_manager.start_game();
+                self.log.push(GameEvent::GameStarted);
 
+                let bundle = self.game_manager.bundle_state();
                 for conn in self.connections.connections.clone() {
-                    conn.do_send(SendPacket(StartGame {}));
+                    conn.do_send(PushEvent(ServerEvent::GameStarted { bundle: bundle.clone() }));
                 }
 
-                Ok(PacketResponse::Unit)
+                Ok(PacketResponse::StartGameOk)
             }
             
             Packet::FinishGame { } => {
-                log::info!("Finishing game: {:#?}", self.id);
-                self.session_state = SessionState::Lobby;
-                self.game_manager.reset_game_state();
-                
-                for conn in self.connections.connections.clone() {
-                    conn.do_send(SendPacket(Packet::FinishGame {}));
-                }
-                
-                Ok(PacketResponse::Unit )
+                self.check_host(msg.sender)?;
+                self.finish_game();
+                Ok(PacketResponse::FinishGameOk)
             }
 
-            _ => {
-                Err(PacketError::CipaChuj)
+            packet => {
+                Err(PacketError::UnsupportedPacket { packet: packet.name() })
             }
         }
     }
@@ -387,3 +654,107 @@ impl Handler<Dump> for Session {
         log::info!("GameBundle: {:#?}", self.game_manager.bundle_state());
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix::Arbiter;
+    use crate::api_structures::card_game::deck::Deck;
+    use crate::api_structures::card_game::test_deck;
+    use serde_json::json;
+
+    fn deck(min_players: i32, max_players: i32) -> Deck {
+        let mut deck = test_deck::deck();
+        deck["meta"]["min_players"] = json!(min_players);
+        deck["meta"]["max_players"] = json!(max_players);
+        serde_json::from_value(deck).unwrap()
+    }
+
+    fn bundle(min_players: i32, max_players: i32) -> DeckBundle {
+        deck(min_players, max_players).into_bundle().unwrap()
+    }
+
+    fn user(n: u128) -> UserId {
+        Uuid::from_u128(n)
+    }
+
+    /// Starts a session hosted by `user(1)` with `players` players already joined.
+    async fn session(bundle: DeckBundle, players: u128) -> Addr<Session> {
+        let (addr, _) = Session::init(&Arbiter::current(), user(1), "p1".to_string(), SessionManager::new().start(), SessionCode::gen(), bundle, 1);
+        for n in 1..=players {
+            join(&addr, n).await.unwrap();
+        }
+        addr
+    }
+
+    async fn join(addr: &Addr<Session>, n: u128) -> Result<SessionConnection, SessionError> {
+        addr.send(AddPlayer { id: user(n), username: format!("p{}", n), is_host: n == 1, session_addr: addr.clone() })
+            .await
+            .unwrap()
+    }
+
+    async fn send(addr: &Addr<Session>, packet: Packet) -> Result<PacketResponse, PacketError> {
+        send_as(addr, 1, packet).await
+    }
+
+    async fn send_as(addr: &Addr<Session>, n: u128, packet: Packet) -> Result<PacketResponse, PacketError> {
+        addr.send(ClientPacket { sender: user(n), packet }).await.unwrap()
+    }
+
+    async fn host(addr: &Addr<Session>) -> String {
+        addr.send(GetHostId()).await.unwrap()
+    }
+
+    fn set_deck(min_players: i32, max_players: i32) -> Packet {
+        Packet::SetDeck { deck: Box::new(deck(min_players, max_players)) }
+    }
+
+    #[actix_web::test]
+    async fn joins_past_max_players_are_rejected() {
+        let addr = session(bundle(0, 2), 2).await;
+        assert!(matches!(join(&addr, 3).await, Err(SessionError::SessionFull)));
+        // a player who is already seated can always come back
+        assert!(join(&addr, 2).await.is_ok());
+    }
+
+    #[actix_web::test]
+    async fn start_needs_min_players() {
+        let addr = session(bundle(3, 0), 2).await;
+        assert!(matches!(
+            send(&addr, Packet::StartGame {}).await,
+            Err(PacketError::NotEnoughPlayers { required: 3, present: 2 })
+        ));
+        join(&addr, 3).await.unwrap();
+        assert!(send(&addr, Packet::StartGame {}).await.is_ok());
+    }
+
+    #[actix_web::test]
+    async fn only_the_host_leaving_hands_over_the_session() {
+        let addr = session(bundle(0, 0), 3).await;
+        send_as(&addr, 3, Packet::PlayerLeft { id: user(3) }).await.unwrap();
+        assert_eq!(host(&addr).await, user(1).to_string());
+
+        send(&addr, Packet::PlayerLeft { id: user(1) }).await.unwrap();
+        assert_eq!(host(&addr).await, user(2).to_string());
+        assert!(send_as(&addr, 2, Packet::StartGame {}).await.is_ok());
+    }
+
+    #[actix_web::test]
+    async fn set_deck_checks_the_players_at_the_table() {
+        let addr = session(bundle(0, 0), 3).await;
+        assert!(matches!(
+            send(&addr, set_deck(0, 2)).await,
+            Err(PacketError::TooManyPlayers { allowed: 2, present: 3 })
+        ));
+        // the lobby can wait for more friends, only starting needs the minimum
+        assert!(matches!(send(&addr, set_deck(4, 0)).await, Ok(PacketResponse::SetDeckOk { .. })));
+        assert!(matches!(
+            send(&addr, Packet::StartGame {}).await,
+            Err(PacketError::NotEnoughPlayers { required: 4, present: 3 })
+        ));
+        assert!(matches!(send(&addr, set_deck(2, 3)).await, Ok(PacketResponse::SetDeckOk { .. })));
+
+        // the new limits apply to later joins
+        assert!(matches!(join(&addr, 4).await, Err(SessionError::SessionFull)));
+    }
+}
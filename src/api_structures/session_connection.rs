@@ -1,19 +1,23 @@
-use crate::api_structures::{messages::*, packet_parser::deserialize_json};
+use crate::api_structures::{messages::*, packet_parser::{deserialize_json, peek_request_id}};
 
 use super::session::Session;
 use super::{id::*, packet_parser::PacketResponse};
-use actix::{Actor, Addr, Handler, StreamHandler};
+use actix::{Actor, ActorContext, ActorFutureExt, Addr, AsyncContext, Handler, StreamHandler, WrapFuture};
 use actix_web_actors::ws;
-use futures::executor::block_on;
+use std::time::Instant;
 use uuid::Uuid;
-use crate::api_structures::managers::game_manager::GameBundle;
-use crate::api_structures::packet_parser::{Packet, PacketError};
+use crate::api_structures::config;
+use crate::api_structures::managers::game_manager::{CardResult, GameBundle};
+use crate::api_structures::packet_parser::{PacketError, RequestId, ServerEvent};
 
 pub struct SessionConnection {
     session: Addr<Session>,
     user_id: UserId,
     id: Uuid,
     is_admin: bool,
+    resume: Option<(GameBundle, Option<CardResult>)>,
+    last_heartbeat: Instant,
+    invalid_packets: usize,
 }
 
 impl SessionConnection {
@@ -23,18 +27,58 @@ impl SessionConnection {
             session,
             id: Uuid::new_v4(),
             is_admin,
+            resume: None,
+            last_heartbeat: Instant::now(),
+            invalid_packets: 0,
         }
     }
+
+    /// State sent to the client as soon as the socket is up, used when a player reconnects mid game.
+    pub fn resume(mut self, bundle: GameBundle, card: Option<CardResult>) -> Self {
+        self.resume = Some((bundle, card));
+        self
+    }
 }
 
 impl Actor for SessionConnection {
     type Context = ws::WebsocketContext<Self>;
+
+    fn started(&mut self, ctx: &mut Self::Context) {
+        let timeout = config::client_timeout();
+        ctx.run_interval(config::heartbeat_interval(), move |conn, ctx| {
+            if Instant::now().duration_since(conn.last_heartbeat) > timeout {
+                log::info!("Connection {:#?} timed out", conn.id);
+                // stopping runs `stopped`, which hands the player over to the disconnect handling
+                ctx.stop();
+                return;
+            }
+            ctx.ping(b"");
+        });
+
+        if let Some((bundle, card)) = self.resume.take() {
+            if let Some(card) = card {
+                push(ctx, &ServerEvent::CardResult { card, bundle: bundle.clone() });
+            }
+            push(ctx, &ServerEvent::StateUpdate { bundle });
+        }
+    }
+
+    fn stopped(&mut self, ctx: &mut Self::Context) {
+        self.session.do_send(ConnectionClosed(self.user_id, ctx.address()));
+    }
 }
 
 impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for SessionConnection {
     fn handle(&mut self, msg: Result<ws::Message, ws::ProtocolError>, ctx: &mut Self::Context) {
+        if msg.is_ok() {
+            self.last_heartbeat = Instant::now();
+        }
         match msg {
             Ok(ws::Message::Ping(msg)) => ctx.pong(&msg),
+            Ok(ws::Message::Close(reason)) => {
+                ctx.close(reason);
+                ctx.stop();
+            }
             Ok(ws::Message::Text(text)) => {
                
                 if  text == "dump" {
@@ -43,38 +87,56 @@ impl StreamHandler<Result<ws::Message, ws::ProtocolError>> for SessionConnection
                 }
                 
                 log::info!("Received text: {:?}", text);
-                let packet = deserialize_json(&text);
-                log::info!("Received packet: {:?}", packet);
-                let response = block_on(async { self.session.send(SendPacket(packet)).await? });
-
-                
-                
-                match response {
-                    Ok(resp) => {
-                        if let PacketResponse::Unit = resp {
-                            return;
-                        }
-                        log::info!("Response: {:?}", resp);
-                        ctx.text(serde_json::to_string(&resp).unwrap());
-                        match resp {
-                            PacketResponse::CloseSessionOk => {
-                                ()
-                            }
-                            _ => {
-                                ctx.text(serde_json::to_string(&PacketResponse::UpdateStateOk { bundle: resp.get_bundle().unwrap() }).unwrap());
-                            }
-                        }
-                    }
+                let request = match deserialize_json(&text) {
+                    Ok(request) => request,
                     Err(err) => {
-                        ctx.text(serde_json::to_string(&err).expect("Failed to serialize Error"));
+                        self.invalid_packets += 1;
+                        log::info!("Invalid packet #{} on connection {:#?}: {:?}", self.invalid_packets, self.id, err);
+                        reply(ctx, peek_request_id(&text), Err(err));
+                        if self.invalid_packets >= config::max_invalid_packets() {
+                            ctx.close(Some(ws::CloseReason {
+                                code: ws::CloseCode::Policy,
+                                description: Some("too many invalid packets".to_string()),
+                            }));
+                            ctx.stop();
+                        }
+                        return;
                     }
-                }
+                };
+                log::info!("Received packet: {:?}", request.packet);
+                let request_id = request.request_id;
+                let response = self.session.send(ClientPacket { sender: self.user_id, packet: request.packet });
+
+                // `wait` holds back the next frame until this one is answered, so replies keep the packet order
+                ctx.wait(response.into_actor(self).map(move |response, _conn, ctx| {
+                    reply(ctx, request_id, response.map_err(PacketError::from).and_then(|response| response));
+                }));
             }
             _ => (),
         }
     }
 }
 
+fn reply(ctx: &mut ws::WebsocketContext<SessionConnection>, request_id: Option<RequestId>, response: Result<PacketResponse, PacketError>) {
+    match response {
+        Ok(resp) => {
+            log::info!("Response: {:?}", resp);
+            ctx.text(serde_json::to_string(&resp.frame(request_id)).unwrap());
+            // Every packet gets exactly one reply, the new state follows as an event.
+            if let Some(bundle) = resp.get_bundle() {
+                push(ctx, &ServerEvent::StateUpdate { bundle });
+            }
+        }
+        Err(err) => {
+            ctx.text(serde_json::to_string(&err.frame(request_id)).expect("Failed to serialize Error"));
+        }
+    }
+}
+
+fn push(ctx: &mut ws::WebsocketContext<SessionConnection>, event: &ServerEvent) {
+    ctx.text(serde_json::to_string(event).expect("Failed to serialize ServerEvent"));
+}
+
 impl Handler<TestMessage> for SessionConnection {
     type Result = ();
 
@@ -94,7 +156,7 @@ impl Handler<ConnectWithSession> for SessionConnection {
     type Result = ();
 
     fn handle(&mut self, msg: ConnectWithSession, _ctx: &mut Self::Context) -> Self::Result {
-        self.session.do_send(AddConnection(msg.0))
+        self.session.do_send(AddConnection(self.user_id, msg.0))
     }
 }
 
@@ -106,36 +168,10 @@ impl Handler<CloseSessionConnection> for SessionConnection {
     }
 }
 
-impl Handler<PlayerUpdate> for SessionConnection {
+impl Handler<PushEvent> for SessionConnection {
     type Result = ();
 
-    fn handle(&mut self, msg: PlayerUpdate, ctx: &mut Self::Context) -> Self::Result {
-
-        let resp = PacketResponse::PlayersUpdateOk { players: msg.0, bundle: GameBundle::default() };
-        ctx.text(
-            serde_json::to_string(&resp ).unwrap(),
-        );
-    }
-}
-
-impl Handler<SendPacket> for SessionConnection {
-    type Result = Result<PacketResponse, PacketError>;
-
-    fn handle(&mut self, msg: SendPacket, ctx: &mut Self::Context) -> Self::Result {
-        
-       let resp = match msg.0 {
-          Packet::CardResult { card, bundle } => Ok(PacketResponse::StartGameOk),
-           Packet::StartGame {} => Ok(PacketResponse::UpdateStateOk { bundle: GameBundle::default() }),
-           Packet::FinishGame {} => Ok(PacketResponse::FinishGameOk),
-          _ => {
-              log::error!("Unknown packet: {:?}", msg.0);
-              Err(PacketError::Errorito)
-          }
-       };
-
-       match resp {
-            Ok(resp) => {ctx.text(serde_json::to_string(&resp).unwrap()); Ok(PacketResponse::AdminTokenOk)},
-            Err(err) => Err(err),
-       }
+    fn handle(&mut self, msg: PushEvent, ctx: &mut Self::Context) -> Self::Result {
+        push(ctx, &msg.0);
     }
 }
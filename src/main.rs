@@ -6,9 +6,10 @@ use actix_web::{get, HttpResponse, Responder};
 use actix_web::{web::Data, App, HttpServer};
 use dotenv::dotenv;
 use env_logger::Env;
-use std::sync::{Arc, Mutex};
+use actix::Actor;
 
 use api_structures::api_state::ApiState;
+use api_structures::managers::session_manager::SessionManager;
 use scopes::game_session::game::game_scope;
 mod api_structures;
 mod auth;
@@ -23,7 +24,7 @@ async fn health() -> impl Responder {
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     dotenv().ok();
-    let api_state = Arc::new(Mutex::new(ApiState::new()));
+    let api_state = ApiState::new(SessionManager::new().start());
 
     env_logger::init_from_env(Env::default().default_filter_or("debug"));
 
@@ -1,9 +1,10 @@
 use crate::api_structures::{
     api_state::ApiState,
     card_game::deck::Deck,
+    game_log::{self, GameLog, LogEntry},
     id::*,
-    managers::game_manager::{GameManager, GameState},
-    messages::ConnectWithSession,
+    managers::session_manager::SessionManagerError,
+    messages::{ConnectWithSession, GetSessionLog, InitSession, JoinSession, ListSessions, UnwrapCode},
     session::SessionCode,
 };
 use actix_web::{
@@ -12,8 +13,8 @@ use actix_web::{
     HttpRequest, HttpResponse, Responder, Scope,
 };
 use actix_web_actors::ws;
+use futures::stream;
 use serde::{Deserialize, Serialize};
-use std::sync::{Arc, Mutex};
 use uuid::Uuid;
 
 #[derive(Serialize, Deserialize)]
@@ -21,6 +22,8 @@ struct CreateSession {
     deck: Deck,
     host_id: String,
     username: String,
+    #[serde(default)]
+    seed: Option<u64>,
 }
 #[derive(Deserialize, Serialize)]
 struct CreateSessionResponse {
@@ -28,40 +31,57 @@ struct CreateSessionResponse {
 }
 
 #[derive(Deserialize, Serialize)]
-struct JoinSession {
+struct JoinQuery {
     session_id: SessionId,
     user_id: UserId,
     username: String,
 }
 
+#[derive(Deserialize)]
+struct LogQuery {
+    host_id: UserId,
+    #[serde(default)]
+    format: Option<String>,
+}
+
 #[derive(Serialize, Deserialize)]
 struct SessionInfo {
     id: SessionId,
     code: String,
+    seed: u64,
 }
 
 #[post("/create")]
 async fn create_game(
-    data: web::Data<Arc<Mutex<ApiState>>>,
+    data: web::Data<ApiState>,
     context: web::Json<CreateSession>,
 ) -> impl Responder {
     if let Ok(host_id) = Uuid::parse_str(&context.host_id) {
-        let username = context.username.clone();
-        let deck = context.deck.clone();
-        let state = data.lock().expect("failed to lock state");
-        let mut session_manager = state
-            .session_manager
-            .lock()
-            .expect("failed to lock session manager");
-        match session_manager.init_session(host_id, username, deck).await {
-            Ok((id, code)) => HttpResponse::Ok().json(SessionInfo {
+        let context = context.into_inner();
+        let deck = match context.deck.into_bundle() {
+            Ok(deck) => deck,
+            Err(report) => return HttpResponse::UnprocessableEntity().json(report),
+        };
+        let init = InitSession {
+            host_id,
+            username: context.username,
+            deck,
+            seed: context.seed,
+        };
+        match data.session_manager.send(init).await {
+            Ok(Ok((id, code, seed))) => HttpResponse::Ok().json(SessionInfo {
                 id,
                 code: code.code,
+                seed,
             }),
-            Err(err) => {
+            Ok(Err(err)) => {
                 log::info!("{:?}", &err);
                 HttpResponse::InternalServerError().finish()
             }
+            Err(err) => {
+                log::error!("Session manager unavailable: {:?}", err);
+                HttpResponse::InternalServerError().finish()
+            }
         }
     } else {
         HttpResponse::BadRequest().body("Invalid host_id")
@@ -70,14 +90,10 @@ async fn create_game(
 
 
 #[get("/games")]
-async fn get_games(data: web::Data<Arc<Mutex<ApiState>>>) -> impl Responder {
-    let state = data.lock().expect("failed to lock state");
-    let session_manager = state
-        .session_manager
-        .lock()
-        .expect("failed to lock session manager");
-
-    let sessions = session_manager.get_games().await;
+async fn get_games(data: web::Data<ApiState>) -> impl Responder {
+    let Ok(sessions) = data.session_manager.send(ListSessions).await else {
+        return HttpResponse::InternalServerError().finish();
+    };
     if sessions.is_empty() {
         return HttpResponse::NoContent().finish();
     }
@@ -86,26 +102,55 @@ async fn get_games(data: web::Data<Arc<Mutex<ApiState>>>) -> impl Responder {
 
 #[post("/unwrap_session_code")]
 async fn unwrap_session_code(
-    data: web::Data<Arc<Mutex<ApiState>>>,
+    data: web::Data<ApiState>,
     context: web::Json<String>,
 ) -> impl Responder {
-    let state = data.lock().expect("fialed to lock state");
-    let session_manager = state
-        .session_manager
-        .lock()
-        .expect("fialed to lock manager");
-    if let Some(session_id) = session_manager.unwrap_code(SessionCode::from(context.0)) {
-        HttpResponse::Ok().json(session_id)
-    } else {
-        HttpResponse::NoContent().finish()
+    match data.session_manager.send(UnwrapCode(SessionCode::from(context.0))).await {
+        Ok(Some(session_id)) => HttpResponse::Ok().json(session_id),
+        Ok(None) => HttpResponse::NotFound().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
     }
 }
 
 #[post("/deck")]
-async fn test_deck(context: web::Json<Deck>) -> impl Responder {
-    let deck = context.into_inner();
-    println!("Got Deck: {:#?}", &deck);
-    HttpResponse::Ok().json(deck)
+async fn validate_deck(context: web::Json<Deck>) -> impl Responder {
+    let mut deck = context.into_inner();
+    let report = deck.validate();
+    if report.is_valid() {
+        HttpResponse::Ok().json(report)
+    } else {
+        HttpResponse::UnprocessableEntity().json(report)
+    }
+}
+
+#[get("/{id}/log")]
+async fn get_log(
+    data: web::Data<ApiState>,
+    id: web::Path<SessionId>,
+    query: web::Query<LogQuery>,
+) -> impl Responder {
+    let request = GetSessionLog { session_id: id.into_inner(), host_id: query.host_id };
+    match data.session_manager.send(request).await {
+        Ok(Ok(entries)) => match query.format.as_deref() {
+            Some("jsonl") => HttpResponse::Ok()
+                .content_type("application/x-ndjson")
+                .streaming(stream::iter(
+                    GameLog { entries }.into_json_lines().map(|line| line.map(web::Bytes::from)),
+                )),
+            _ => HttpResponse::Ok().json(entries),
+        },
+        Ok(Err(SessionManagerError::NotHost)) => HttpResponse::Forbidden().finish(),
+        Ok(Err(_)) => HttpResponse::NotFound().finish(),
+        Err(_) => HttpResponse::InternalServerError().finish(),
+    }
+}
+
+#[post("/replay")]
+async fn replay_log(context: web::Json<Vec<LogEntry>>) -> impl Responder {
+    match game_log::replay(&context) {
+        Ok(bundle) => HttpResponse::Ok().json(bundle),
+        Err(err) => HttpResponse::UnprocessableEntity().json(err),
+    }
 }
 
 /// depprecated
@@ -126,25 +171,22 @@ async fn test_deck(context: web::Json<Deck>) -> impl Responder {
 // }
 
 async fn join_game(
-    data: web::Data<Arc<Mutex<ApiState>>>,
+    data: web::Data<ApiState>,
     stream: web::Payload,
     req: HttpRequest,
 ) -> Result<HttpResponse, actix_web::Error> {
-    let state = data.lock().expect("failed to lock state");
-    let mut session_manager = state
-        .session_manager
-        .lock()
-        .expect("failed to lock session manager");
-    let query = web::Query::<JoinSession>::from_query(req.query_string())?;
-    let session_id: SessionId = query.session_id;
-    let user_id: UserId = query.user_id;
-    let username: String = query.username.clone();
-
-    match session_manager
-        .join_session(session_id, user_id, username)
-        .await
-    {
-        Some(conn) => {
+    let query = web::Query::<JoinQuery>::from_query(req.query_string())?;
+    let join = JoinSession {
+        session_id: query.session_id,
+        user_id: query.user_id,
+        username: query.username.clone(),
+    };
+
+    let Ok(joined) = data.session_manager.send(join).await else {
+        return Ok(HttpResponse::InternalServerError().finish());
+    };
+    match joined {
+        Ok(conn) => {
             let (addr, resp) = ws::WsResponseBuilder::new(conn, &req, stream)
                 .start_with_addr()
                 .expect("cannot create with addr");
@@ -152,7 +194,9 @@ async fn join_game(
             Ok(resp)
         }
 
-        None => Ok(HttpResponse::BadRequest().finish()),
+        Err(SessionManagerError::JoinRejected(err)) => Ok(HttpResponse::Conflict().json(err)),
+        Err(SessionManagerError::SessionNotFound) => Ok(HttpResponse::NotFound().finish()),
+        Err(_) => Ok(HttpResponse::BadRequest().finish()),
     }
 }
 
@@ -162,5 +206,126 @@ pub fn game_scope() -> Scope {
         .route("/join", web::get().to(join_game))
         .service(unwrap_session_code)
         .service(get_games)
-        .service(test_deck)
-}
\ No newline at end of file
+        .service(validate_deck)
+        .service(get_log)
+        .service(replay_log)
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::api_structures::card_game::test_deck;
+    use crate::api_structures::card_game::validation::{DeckDiagnostic, DeckReport};
+    use crate::api_structures::game_log::GameEvent;
+    use crate::api_structures::managers::session_manager::SessionManager;
+    use actix::Actor;
+    use actix_web::{http::StatusCode, test, App};
+    use serde_json::json;
+
+    /// A single card drinking from `table`, anything but `drinks` makes the deck invalid.
+    fn deck(table: &str) -> serde_json::Value {
+        let mut deck = test_deck::deck();
+        deck["cards"] = json!([{
+            "bg": "b",
+            "general_text": "g",
+            "template": "drink {d}",
+            "actions": [{ "type": "GetFromTable", "ident": "d", "table": table, "tags": [] }]
+        }]);
+        deck
+    }
+
+    #[actix_web::test]
+    async fn validate_deck_accepts_valid_decks() {
+        let app = test::init_service(App::new().service(game_scope())).await;
+        let req = test::TestRequest::post().uri("/game/deck").set_json(deck("drinks")).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn validate_deck_answers_422_with_the_report() {
+        let app = test::init_service(App::new().service(game_scope())).await;
+        let req = test::TestRequest::post().uri("/game/deck").set_json(deck("nope")).to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        let report: DeckReport = test::read_body_json(resp).await;
+        assert_eq!(report.errors.len(), 1);
+        assert!(matches!(&report.errors[0], DeckDiagnostic::UnknownTable { table, .. } if table == "nope"));
+    }
+
+    #[actix_web::test]
+    async fn create_game_rejects_invalid_decks() {
+        let state = ApiState::new(SessionManager::new().start());
+        let app = test::init_service(App::new().app_data(web::Data::new(state)).service(game_scope())).await;
+        let req = test::TestRequest::post()
+            .uri("/game/create")
+            .set_json(json!({ "deck": deck("nope"), "host_id": Uuid::nil(), "username": "host" }))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+    }
+
+    #[actix_web::test]
+    async fn log_streams_as_json_lines() {
+        let state = ApiState::new(SessionManager::new().start());
+        let app = test::init_service(App::new().app_data(web::Data::new(state)).service(game_scope())).await;
+        let req = test::TestRequest::post()
+            .uri("/game/create")
+            .set_json(json!({ "deck": deck("drinks"), "host_id": Uuid::nil(), "username": "host", "seed": 3 }))
+            .to_request();
+        let session: SessionInfo = test::call_and_read_body_json(&app, req).await;
+
+        let req = test::TestRequest::get()
+            .uri(&format!("/game/{}/log?format=jsonl&host_id={}", session.id, Uuid::nil()))
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert_eq!(resp.headers().get("content-type").unwrap(), "application/x-ndjson");
+
+        let body = test::read_body(resp).await;
+        let entries: Vec<LogEntry> = std::str::from_utf8(&body)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert!(matches!(entries.as_slice(), [LogEntry { event: GameEvent::SessionCreated { seed: 3, .. }, .. }]));
+    }
+
+    #[actix_web::test]
+    async fn log_is_only_served_to_the_host() {
+        let state = ApiState::new(SessionManager::new().start());
+        let app = test::init_service(App::new().app_data(web::Data::new(state)).service(game_scope())).await;
+        let req = test::TestRequest::post()
+            .uri("/game/create")
+            .set_json(json!({ "deck": deck("drinks"), "host_id": Uuid::nil(), "username": "host" }))
+            .to_request();
+        let session: SessionInfo = test::call_and_read_body_json(&app, req).await;
+
+        let uri = format!("/game/{}/log", session.id);
+        let resp = test::call_service(&app, test::TestRequest::get().uri(&uri).to_request()).await;
+        assert_eq!(resp.status(), StatusCode::BAD_REQUEST);
+
+        let guest = format!("{}?host_id={}", uri, Uuid::from_u128(1));
+        let resp = test::call_service(&app, test::TestRequest::get().uri(&guest).to_request()).await;
+        assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+
+        let host = format!("{}?host_id={}", uri, Uuid::nil());
+        let resp = test::call_service(&app, test::TestRequest::get().uri(&host).to_request()).await;
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+
+    #[actix_web::test]
+    async fn replay_rejects_invalid_decks() {
+        let mut bundle = test_deck::bundle(deck("drinks"));
+        bundle.tables.clear();
+        let app = test::init_service(App::new().service(game_scope())).await;
+        let req = test::TestRequest::post()
+            .uri("/game/replay")
+            .set_json(GameLog::new(1, bundle).entries)
+            .to_request();
+        let resp = test::call_service(&app, req).await;
+        assert_eq!(resp.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        let body: serde_json::Value = test::read_body_json(resp).await;
+        assert_eq!(body["error"], "InvalidDeck");
+    }
+}